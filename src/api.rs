@@ -0,0 +1,335 @@
+//! JSON API for scripts (phone shortcuts, dashboards). It uses the same `Repository` as the
+//! HTML pages. Amounts are decimal strings (e.g. "-21.37") to avoid floating point, signed like
+//! `Money`.
+
+use crate::money::{self, Currency, Money};
+use crate::{AppState, Expense, ExpenseFilter, Repository, Search, SqliteInteger};
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+struct ApiError(StatusCode, String);
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(e: rusqlite::Error) -> Self {
+        eprintln!("Database error: {e}");
+        ApiError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "database error".to_string(),
+        )
+    }
+}
+
+impl From<r2d2::Error> for ApiError {
+    fn from(e: r2d2::Error) -> Self {
+        eprintln!("Can't get a database connection: {e}");
+        ApiError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "database unavailable".to_string(),
+        )
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+fn error(status: StatusCode, message: &str) -> ApiError {
+    ApiError(status, message.to_string())
+}
+
+/// Both sides of a transfer are changed together, through the web UI.
+const PART_OF_TRANSFER: &str = "expense is part of a transfer";
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/accounts", get(accounts))
+        .route("/expenses", get(list_expenses).post(create_expense))
+        .route(
+            "/expenses/:id",
+            get(get_expense).put(update_expense).delete(delete_expense),
+        )
+        .route("/balances", get(balances))
+        .route("/exchange_rates", get(exchange_rates))
+}
+
+#[derive(Serialize)]
+struct ApiAccount {
+    id: SqliteInteger,
+    name: String,
+    currency: Currency,
+}
+
+async fn accounts(State(state): State<AppState>) -> ApiResult<Json<Vec<ApiAccount>>> {
+    state
+        .run(move |repo| {
+            let mut accounts = repo
+                .get_accounts()?
+                .into_iter()
+                .map(|(id, account)| ApiAccount {
+                    id,
+                    name: account.name,
+                    currency: account.currency,
+                })
+                .collect::<Vec<_>>();
+            accounts.sort_by_key(|account| account.id);
+            Ok(Json(accounts))
+        })
+        .await
+}
+
+#[derive(Serialize)]
+struct ApiExpense {
+    id: SqliteInteger,
+    name: String,
+    amount: String,
+    currency: Currency,
+    date: String,
+    account_id: SqliteInteger,
+    category_id: Option<SqliteInteger>,
+    /// Set on both sides of a transfer between own accounts.
+    transfer_id: Option<SqliteInteger>,
+}
+
+impl From<Expense> for ApiExpense {
+    fn from(expense: Expense) -> Self {
+        ApiExpense {
+            id: expense.id,
+            name: expense.name,
+            amount: expense.value.decimal(),
+            currency: expense.value.currency,
+            date: expense.date.format("%Y-%m-%d").to_string(),
+            account_id: expense.account_id,
+            category_id: expense.category_id,
+            transfer_id: expense.transfer_id,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Pagination {
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+/// Newest first, like on the main page.
+async fn list_expenses(
+    State(state): State<AppState>,
+    Query(filter): Query<ExpenseFilter>,
+    Query(pagination): Query<Pagination>,
+) -> ApiResult<Json<Value>> {
+    state
+        .run(move |repo| {
+            let search = Search {
+                filter,
+                ..Search::default()
+            };
+            let limit = pagination.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+            let offset = pagination.offset.unwrap_or(0);
+            let page = repo
+                .find(&search, Some(limit), offset)?
+                .into_iter()
+                .map(ApiExpense::from)
+                .collect::<Vec<_>>();
+            let total = repo.count(&search)?;
+
+            Ok(Json(json!({
+                "expenses": page,
+                "total": total,
+                "limit": limit,
+                "offset": offset,
+            })))
+        })
+        .await
+}
+
+async fn get_expense(
+    State(state): State<AppState>,
+    Path(id): Path<SqliteInteger>,
+) -> ApiResult<Json<ApiExpense>> {
+    state
+        .run(move |repo| {
+            let expense = repo
+                .get(id)?
+                .ok_or(error(StatusCode::NOT_FOUND, "no such expense"))?;
+            Ok(Json(expense.into()))
+        })
+        .await
+}
+
+#[derive(Deserialize)]
+struct ApiNewExpense {
+    name: String,
+    amount: String,
+    date: String,
+    account_id: SqliteInteger,
+    #[serde(default)]
+    category_id: Option<SqliteInteger>,
+}
+
+impl ApiNewExpense {
+    fn parse(&self, repo: &impl Repository) -> ApiResult<(Money, NaiveDate)> {
+        let account = repo
+            .get_accounts()?
+            .remove(&self.account_id)
+            .ok_or(error(StatusCode::UNPROCESSABLE_ENTITY, "no such account"))?;
+        if let Some(category_id) = self.category_id {
+            if !repo.get_categories()?.contains_key(&category_id) {
+                return Err(error(StatusCode::UNPROCESSABLE_ENTITY, "no such category"));
+            }
+        }
+        let amount = money::parse_minor_units(&self.amount, account.currency.minor_digits())
+            .map_err(|_| error(StatusCode::UNPROCESSABLE_ENTITY, "invalid amount"))?;
+        let date = NaiveDate::parse_from_str(&self.date, "%Y-%m-%d").map_err(|_| {
+            error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid date, expected YYYY-MM-DD",
+            )
+        })?;
+        Ok((Money::new(amount, account.currency), date))
+    }
+}
+
+async fn create_expense(
+    State(state): State<AppState>,
+    Json(new_expense): Json<ApiNewExpense>,
+) -> ApiResult<(StatusCode, Json<ApiExpense>)> {
+    state
+        .run(move |repo| {
+            let (value, date) = new_expense.parse(&repo)?;
+            let id = repo.add(
+                new_expense.name,
+                value,
+                date,
+                new_expense.account_id.to_string(),
+                new_expense.category_id,
+            )?;
+            let expense = repo
+                .get(id)?
+                .ok_or(error(StatusCode::NOT_FOUND, "no such expense"))?;
+            Ok((StatusCode::CREATED, Json(expense.into())))
+        })
+        .await
+}
+
+async fn update_expense(
+    State(state): State<AppState>,
+    Path(id): Path<SqliteInteger>,
+    Json(expense): Json<ApiNewExpense>,
+) -> ApiResult<Json<ApiExpense>> {
+    state
+        .run(move |repo| {
+            let existing = repo
+                .get(id)?
+                .ok_or(error(StatusCode::NOT_FOUND, "no such expense"))?;
+            if existing.transfer_id.is_some() {
+                return Err(error(StatusCode::CONFLICT, PART_OF_TRANSFER));
+            }
+            let (value, date) = expense.parse(&repo)?;
+            repo.update(
+                id,
+                expense.name,
+                value,
+                date,
+                expense.account_id.to_string(),
+                expense.category_id,
+            )?;
+            let expense = repo
+                .get(id)?
+                .ok_or(error(StatusCode::NOT_FOUND, "no such expense"))?;
+            Ok(Json(expense.into()))
+        })
+        .await
+}
+
+async fn delete_expense(
+    State(state): State<AppState>,
+    Path(id): Path<SqliteInteger>,
+) -> ApiResult<StatusCode> {
+    state
+        .run(move |repo| {
+            let expense = repo
+                .get(id)?
+                .ok_or(error(StatusCode::NOT_FOUND, "no such expense"))?;
+            if expense.transfer_id.is_some() {
+                return Err(error(StatusCode::CONFLICT, PART_OF_TRANSFER));
+            }
+            repo.delete(id)?;
+            Ok(StatusCode::NO_CONTENT)
+        })
+        .await
+}
+
+async fn balances(State(state): State<AppState>) -> ApiResult<Json<Value>> {
+    state
+        .run(move |repo| {
+            let per_currency = repo
+                .balance()?
+                .into_iter()
+                .map(|(currency, balance)| (currency.to_string(), json!(balance.decimal())))
+                .collect::<serde_json::Map<_, _>>();
+            let mut per_account = repo
+                .get_balance_per_account()?
+                .into_iter()
+                .map(|(account_id, balance)| {
+                    json!({
+                        "account_id": account_id,
+                        "balance": balance.decimal(),
+                        "currency": balance.currency,
+                    })
+                })
+                .collect::<Vec<_>>();
+            per_account.sort_by_key(|balance| balance["account_id"].as_i64());
+
+            Ok(Json(json!({
+                "per_currency": per_currency,
+                "per_account": per_account,
+            })))
+        })
+        .await
+}
+
+#[derive(Deserialize)]
+struct RatesQuery {
+    #[serde(default, deserialize_with = "crate::empty_as_none")]
+    date: Option<NaiveDate>,
+}
+
+/// Approximate value of one unit of each currency in EUR on the given date, today by default.
+/// Currencies without any known rate are left out.
+async fn exchange_rates(
+    State(state): State<AppState>,
+    Query(query): Query<RatesQuery>,
+) -> ApiResult<Json<Value>> {
+    let date = query
+        .date
+        .unwrap_or_else(|| chrono::offset::Local::now().date_naive());
+    state
+        .run(move |repo| {
+            let exchange_rates = repo.get_exchange_rates()?;
+            let rates = Currency::all()
+                .filter_map(|currency| {
+                    let rate = exchange_rates.to_eur(currency, date)?;
+                    Some((currency.to_string(), json!(rate)))
+                })
+                .collect::<serde_json::Map<_, _>>();
+            Ok(Json(json!({
+                "base": "EUR",
+                "date": date.format("%Y-%m-%d").to_string(),
+                "rates": rates,
+            })))
+        })
+        .await
+}
@@ -0,0 +1,153 @@
+//! Inline SVG charts for the stats page. They're rendered on the server, so they work without
+//! JavaScript, and use `currentColor` to follow the light or dark theme of Water.css.
+
+use crate::money::Money;
+
+const WIDTH: f64 = 600.0;
+/// Height of vertical charts, including labels.
+const HEIGHT: f64 = 200.0;
+/// Room for values above the plot and labels below it.
+const MARGIN: f64 = 20.0;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn start(title: &str, height: f64) -> String {
+    format!(
+        r#"<svg viewBox="0 0 {WIDTH} {height}" width="100%" role="img" aria-label="{}" font-size="10" fill="currentColor">"#,
+        escape(title)
+    )
+}
+
+/// Text centered on `x`.
+fn text(x: f64, y: f64, text: &str) -> String {
+    format!(
+        r#"<text x="{x:.1}" y="{y:.1}" text-anchor="middle">{}</text>"#,
+        escape(text)
+    )
+}
+
+/// Vertical bars, e.g. spending per month, with labels below and values above them. Negative
+/// values have no bar.
+pub fn bars(title: &str, bars: &[(String, Money)]) -> String {
+    let max = bars
+        .iter()
+        .map(|(_, value)| value.approx_major_units())
+        .fold(0.0, f64::max);
+    let plot_height = HEIGHT - 2.0 * MARGIN;
+    let slot = WIDTH / bars.len().max(1) as f64;
+    let mut svg = start(title, HEIGHT);
+    for (i, (label, value)) in bars.iter().enumerate() {
+        let height = match max {
+            max if max > 0.0 => value.approx_major_units().max(0.0) / max * plot_height,
+            _ => 0.0,
+        };
+        let x = i as f64 * slot;
+        let top = MARGIN + plot_height - height;
+        svg.push_str(&format!(
+            r#"<rect x="{:.1}" y="{top:.1}" width="{:.1}" height="{height:.1}" opacity="0.6"><title>{}: {}</title></rect>"#,
+            x + slot * 0.1,
+            slot * 0.8,
+            escape(label),
+            value,
+        ));
+        svg.push_str(&text(x + slot / 2.0, top - 4.0, &value.to_string()));
+        svg.push_str(&text(x + slot / 2.0, HEIGHT - 6.0, label));
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// Horizontal bars, e.g. spending per category, one per row with its label on the left.
+/// Negative values have no bar.
+pub fn horizontal_bars(title: &str, bars: &[(String, Money)]) -> String {
+    const ROW: f64 = 20.0;
+    const LABEL_WIDTH: f64 = 150.0;
+    const VALUE_WIDTH: f64 = 80.0;
+    let max = bars
+        .iter()
+        .map(|(_, value)| value.approx_major_units())
+        .fold(0.0, f64::max);
+    let mut svg = start(title, ROW * bars.len() as f64);
+    for (i, (label, value)) in bars.iter().enumerate() {
+        let width = match max {
+            max if max > 0.0 => {
+                value.approx_major_units().max(0.0) / max * (WIDTH - LABEL_WIDTH - VALUE_WIDTH)
+            }
+            _ => 0.0,
+        };
+        let y = i as f64 * ROW;
+        let label = label.chars().take(25).collect::<String>();
+        svg.push_str(&format!(
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{}</text>"#,
+            LABEL_WIDTH - 5.0,
+            y + 14.0,
+            escape(&label),
+        ));
+        svg.push_str(&format!(
+            r#"<rect x="{LABEL_WIDTH}" y="{:.1}" width="{width:.1}" height="{:.1}" opacity="0.6"><title>{}: {}</title></rect>"#,
+            y + 4.0,
+            ROW - 8.0,
+            escape(&label),
+            value,
+        ));
+        svg.push_str(&format!(
+            r#"<text x="{:.1}" y="{:.1}">{}</text>"#,
+            LABEL_WIDTH + width + 5.0,
+            y + 14.0,
+            value,
+        ));
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// A line through the points, e.g. net worth per month, with labels below and values above
+/// them. Zero is marked with a dashed line when there are negative values.
+pub fn line(title: &str, points: &[(String, Money)]) -> String {
+    let values = points
+        .iter()
+        .map(|(_, value)| value.approx_major_units())
+        .collect::<Vec<_>>();
+    let max = values.iter().copied().fold(0.0, f64::max);
+    let min = values.iter().copied().fold(0.0, f64::min);
+    let range = if max > min { max - min } else { 1.0 };
+    let plot_height = HEIGHT - 2.0 * MARGIN;
+    let slot = WIDTH / points.len().max(1) as f64;
+    let x = |i: usize| i as f64 * slot + slot / 2.0;
+    let y = |value: f64| MARGIN + (max - value) / range * plot_height;
+
+    let mut svg = start(title, HEIGHT);
+    if min < 0.0 {
+        svg.push_str(&format!(
+            r#"<line x1="0" y1="{0:.1}" x2="{WIDTH}" y2="{0:.1}" stroke="currentColor" stroke-dasharray="4" opacity="0.4"/>"#,
+            y(0.0),
+        ));
+    }
+    let polyline = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| format!("{:.1},{:.1}", x(i), y(*value)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    svg.push_str(&format!(
+        r#"<polyline points="{polyline}" fill="none" stroke="currentColor" stroke-width="2"/>"#
+    ));
+    for (i, ((label, value), major_units)) in points.iter().zip(&values).enumerate() {
+        svg.push_str(&format!(
+            r#"<circle cx="{:.1}" cy="{:.1}" r="3"><title>{}: {}</title></circle>"#,
+            x(i),
+            y(*major_units),
+            escape(label),
+            value,
+        ));
+        svg.push_str(&text(x(i), y(*major_units) - 6.0, &value.to_string()));
+        svg.push_str(&text(x(i), HEIGHT - 6.0, label));
+    }
+    svg.push_str("</svg>");
+    svg
+}
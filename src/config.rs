@@ -0,0 +1,128 @@
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+const USAGE: &str = "Usage: casa [OPTIONS] [import-rates <FILE>...]
+
+Without a command, casa serves the web UI. `import-rates` adds the exchange rate history from
+ECB eurofxref-hist (CSV or XML) or NBP table A (JSON or XML) files to the database and exits.
+
+Options (each can also be set with the given environment variable):
+    --config <PATH>        TOML config file [CASA_CONFIG]
+    --listen <ADDRESS>     IP address to listen on [CASA_LISTEN] (default: 127.0.0.1)
+    --port <PORT>          TCP port to listen on [CASA_PORT] (default: 2137)
+    --database <PATH>      SQLite database [CASA_DATABASE] (default: ./db.sqlite)
+    --unix-socket <PATH>   listen on a Unix socket instead of TCP [CASA_UNIX_SOCKET]
+    --help                 show this message
+
+Command-line options take precedence over environment variables, which take precedence over
+the config file, whose keys are `listen`, `port`, `database` and `unix_socket`.";
+
+pub struct Config {
+    pub listen: IpAddr,
+    pub port: u16,
+    pub database: PathBuf,
+    pub unix_socket: Option<PathBuf>,
+    pub command: Command,
+}
+
+pub enum Command {
+    Serve,
+    ImportRates(Vec<PathBuf>),
+}
+
+/// Every option is optional at every layer; the layers are merged in `Config::load`.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Layer {
+    listen: Option<IpAddr>,
+    port: Option<u16>,
+    database: Option<PathBuf>,
+    unix_socket: Option<PathBuf>,
+}
+
+impl Layer {
+    fn or(self, other: Layer) -> Layer {
+        Layer {
+            listen: self.listen.or(other.listen),
+            port: self.port.or(other.port),
+            database: self.database.or(other.database),
+            unix_socket: self.unix_socket.or(other.unix_socket),
+        }
+    }
+}
+
+fn parse<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {name}: {value:?}"))
+}
+
+impl Config {
+    /// Read the configuration from the command line, the environment and the config file.
+    pub fn load() -> Result<Config, String> {
+        let mut args = std::env::args().skip(1);
+        let mut cli = Layer::default();
+        let mut config_path = std::env::var_os("CASA_CONFIG").map(PathBuf::from);
+        let mut command = Command::Serve;
+        while let Some(arg) = args.next() {
+            if arg == "--help" {
+                println!("{USAGE}");
+                std::process::exit(0);
+            }
+            if !arg.starts_with("--") {
+                match (&mut command, arg.as_str()) {
+                    (Command::Serve, "import-rates") => command = Command::ImportRates(Vec::new()),
+                    (Command::ImportRates(paths), _) => paths.push(arg.into()),
+                    _ => return Err(format!("unknown command {arg}\n\n{USAGE}")),
+                }
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or(format!("missing value for {arg}\n\n{USAGE}"))?;
+            match arg.as_str() {
+                "--config" => config_path = Some(value.into()),
+                "--listen" => cli.listen = Some(parse(&arg, &value)?),
+                "--port" => cli.port = Some(parse(&arg, &value)?),
+                "--database" => cli.database = Some(value.into()),
+                "--unix-socket" => cli.unix_socket = Some(value.into()),
+                _ => return Err(format!("unknown option {arg}\n\n{USAGE}")),
+            }
+        }
+        if matches!(&command, Command::ImportRates(paths) if paths.is_empty()) {
+            return Err(format!("missing files for import-rates\n\n{USAGE}"));
+        }
+
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let env = Layer {
+            listen: var("CASA_LISTEN")
+                .map(|v| parse("CASA_LISTEN", &v))
+                .transpose()?,
+            port: var("CASA_PORT")
+                .map(|v| parse("CASA_PORT", &v))
+                .transpose()?,
+            database: var("CASA_DATABASE").map(PathBuf::from),
+            unix_socket: var("CASA_UNIX_SOCKET").map(PathBuf::from),
+        };
+
+        let file = match config_path {
+            Some(path) => {
+                let content = std::fs::read_to_string(&path)
+                    .map_err(|e| format!("can't read {}: {e}", path.display()))?;
+                toml::from_str(&content)
+                    .map_err(|e| format!("can't parse {}: {e}", path.display()))?
+            }
+            None => Layer::default(),
+        };
+
+        let merged = cli.or(env).or(file);
+        Ok(Config {
+            listen: merged.listen.unwrap_or(IpAddr::from([127, 0, 0, 1])),
+            port: merged.port.unwrap_or(2137),
+            database: merged.database.unwrap_or("./db.sqlite".into()),
+            unix_socket: merged.unix_socket,
+            command,
+        })
+    }
+}
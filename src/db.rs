@@ -0,0 +1,57 @@
+use rusqlite::functions::FunctionFlags;
+use rusqlite::Connection;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub type Pool = r2d2::Pool<ConnectionManager>;
+
+/// Opens SQLite connections for the pool, configured so that several household members can
+/// use casa at the same time.
+pub struct ConnectionManager {
+    path: PathBuf,
+}
+
+impl r2d2::ManageConnection for ConnectionManager {
+    type Connection = Connection;
+    type Error = rusqlite::Error;
+
+    fn connect(&self) -> Result<Connection, rusqlite::Error> {
+        let connection = Connection::open(&self.path)?;
+        // With WAL readers don't wait for the writer, and the timeout makes writers wait for
+        // each other instead of failing with "database is locked".
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.busy_timeout(Duration::from_secs(5))?;
+        // SQLite's `lower` only knows ASCII letters, so it wouldn't match "Żabka" with "żabka".
+        connection.create_scalar_function(
+            "unicode_lower",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            |context| Ok(context.get::<String>(0)?.to_lowercase()),
+        )?;
+        Ok(connection)
+    }
+
+    fn is_valid(&self, connection: &mut Connection) -> Result<(), rusqlite::Error> {
+        connection.execute_batch("")
+    }
+
+    fn has_broken(&self, _: &mut Connection) -> bool {
+        false
+    }
+}
+
+pub fn open(path: PathBuf) -> Result<Pool, r2d2::Error> {
+    r2d2::Pool::new(ConnectionManager { path })
+}
+
+/// A fresh database in memory. Each connection to `:memory:` opens a separate one, so the pool
+/// holds just one.
+#[cfg(test)]
+pub fn open_in_memory() -> Pool {
+    r2d2::Pool::builder()
+        .max_size(1)
+        .build(ConnectionManager {
+            path: PathBuf::from(":memory:"),
+        })
+        .unwrap()
+}
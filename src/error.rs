@@ -0,0 +1,71 @@
+use crate::{render_footer, HTML_HEADER};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use minijinja::render;
+use std::fmt::{Display, Formatter};
+
+/// An error in a handler, shown to the user as a page instead of dropping the connection.
+pub enum AppError {
+    Database(rusqlite::Error),
+    /// No connection became available in time.
+    Unavailable(r2d2::Error),
+    NotFound,
+    /// Invalid input which can't be shown next to a form field. The message is in Polish.
+    BadRequest(String),
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(e: rusqlite::Error) -> Self {
+        AppError::Database(e)
+    }
+}
+
+impl From<r2d2::Error> for AppError {
+    fn from(e: r2d2::Error) -> Self {
+        AppError::Unavailable(e)
+    }
+}
+
+impl Display for AppError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::Database(e) => write!(f, "database error: {e}"),
+            AppError::Unavailable(e) => write!(f, "can't get a database connection: {e}"),
+            AppError::NotFound => write!(f, "not found"),
+            AppError::BadRequest(message) => write!(f, "bad request: {message}"),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            AppError::Database(_) => {
+                eprintln!("{self}");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Błąd bazy danych, spróbuj ponownie.".to_string(),
+                )
+            }
+            AppError::Unavailable(_) => {
+                eprintln!("{self}");
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Baza danych jest zajęta, spróbuj ponownie.".to_string(),
+                )
+            }
+            AppError::NotFound => (StatusCode::NOT_FOUND, "Nie ma takiej strony.".to_string()),
+            AppError::BadRequest(message) => (StatusCode::BAD_REQUEST, message),
+        };
+        let r = render!(
+r#"{{ header }}
+<p><mark>{{ message | e }}</mark></p>
+<p><a href="/">Wróć</a></p>
+{{ footer }}"#,
+            header => HTML_HEADER,
+            footer => render_footer(),
+            message => message,
+        );
+        (status, Html(r)).into_response()
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{Account, Category, Currency, Expense, Money, NetWorth, SqliteInteger};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// An expense with raw values, as opposed to the `Display`-based `Serialize` of `Expense`.
+/// The amount is signed like `Money`.
+#[derive(Serialize)]
+struct ExportedExpense {
+    id: SqliteInteger,
+    date: String,
+    name: String,
+    amount: String,
+    currency: String,
+    account: String,
+    category: Option<String>,
+}
+
+fn to_exported(
+    expense: &Expense,
+    accounts: &HashMap<SqliteInteger, Account>,
+    categories: &HashMap<SqliteInteger, Category>,
+) -> ExportedExpense {
+    ExportedExpense {
+        id: expense.id,
+        date: expense.date.format("%Y-%m-%d").to_string(),
+        name: expense.name.clone(),
+        amount: expense.value.decimal(),
+        currency: expense.value.currency.to_string(),
+        account: accounts[&expense.account_id].name.clone(),
+        category: expense.category_id.map(|id| categories[&id].name.clone()),
+    }
+}
+
+pub fn to_csv(
+    expenses: &[&Expense],
+    accounts: &HashMap<SqliteInteger, Account>,
+    categories: &HashMap<SqliteInteger, Category>,
+) -> String {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for expense in expenses {
+        writer
+            .serialize(to_exported(expense, accounts, categories))
+            .unwrap();
+    }
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+pub fn to_json(
+    expenses: &[&Expense],
+    accounts: &HashMap<SqliteInteger, Account>,
+    categories: &HashMap<SqliteInteger, Category>,
+) -> String {
+    let exported = expenses
+        .iter()
+        .map(|expense| to_exported(expense, accounts, categories))
+        .collect::<Vec<_>>();
+    serde_json::to_string_pretty(&exported).unwrap()
+}
+
+/// A row per month with a column per account, per currency and the total in `currency`.
+pub fn net_worth_to_csv(
+    history: &[NetWorth],
+    accounts: &HashMap<SqliteInteger, Account>,
+    currency: Currency,
+) -> String {
+    let ids = crate::account_order(accounts);
+    let currencies = crate::account_currencies(accounts);
+    let mut header = vec!["month".to_string()];
+    header.extend(
+        ids.iter()
+            .map(|id| format!("[{}] {}", accounts[id].currency, accounts[id].name)),
+    );
+    header.extend(currencies.iter().map(Currency::to_string));
+    header.push(format!("total_{currency}"));
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(&header).unwrap();
+    for net_worth in history {
+        let mut record = vec![net_worth.month.param()];
+        record.extend(ids.iter().map(|id| net_worth.per_account[id].decimal()));
+        record.extend(currencies.iter().map(|currency| {
+            net_worth
+                .per_currency
+                .get(currency)
+                .map(Money::decimal)
+                .unwrap_or_default()
+        }));
+        record.push(net_worth.total.decimal());
+        writer.write_record(&record).unwrap();
+    }
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// The longest `<NAME>` OFX allows.
+const OFX_NAME_LENGTH: usize = 32;
+
+/// OFX 2 bank statements, one per account. `balances` are current balances per account, which
+/// OFX requires even if only some transactions are exported.
+pub fn to_ofx(
+    expenses: &[&Expense],
+    accounts: &HashMap<SqliteInteger, Account>,
+    balances: &HashMap<SqliteInteger, Money>,
+) -> String {
+    let now = chrono::offset::Utc::now()
+        .format("%Y%m%d%H%M%S")
+        .to_string();
+    let mut ofx = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>
+<?OFX OFXHEADER="200" VERSION="220" SECURITY="NONE" OLDFILEUID="NONE" NEWFILEUID="NONE"?>
+<OFX>
+<SIGNONMSGSRSV1><SONRS><STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS><DTSERVER>{now}</DTSERVER><LANGUAGE>POL</LANGUAGE></SONRS></SIGNONMSGSRSV1>
+<BANKMSGSRSV1>
+"#
+    );
+
+    let mut account_ids = expenses.iter().map(|e| e.account_id).collect::<Vec<_>>();
+    account_ids.sort_unstable();
+    account_ids.dedup();
+    for account_id in account_ids {
+        let account = &accounts[&account_id];
+        let transactions = expenses
+            .iter()
+            .filter(|e| e.account_id == account_id)
+            .copied()
+            .collect::<Vec<_>>();
+        let start = transactions.iter().map(|e| e.date).min().unwrap();
+        let end = transactions.iter().map(|e| e.date).max().unwrap();
+
+        ofx.push_str(&format!(
+            "<STMTTRNRS><TRNUID>{account_id}</TRNUID><STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>\n<STMTRS><CURDEF>{}</CURDEF>\n<BANKACCTFROM><BANKID>casa</BANKID><ACCTID>{account_id}</ACCTID><ACCTTYPE>CHECKING</ACCTTYPE></BANKACCTFROM>\n<BANKTRANLIST><DTSTART>{}</DTSTART><DTEND>{}</DTEND>\n",
+            account.currency,
+            start.format("%Y%m%d"),
+            end.format("%Y%m%d"),
+        ));
+        for expense in transactions {
+            // OFX amounts are from the account's point of view: spending is negative.
+            let amount = -expense.value;
+            let name = expense
+                .name
+                .chars()
+                .take(OFX_NAME_LENGTH)
+                .collect::<String>();
+            // Longer names are kept whole in the memo.
+            let memo = if name == expense.name {
+                String::new()
+            } else {
+                format!("<MEMO>{}</MEMO>", escape_xml(&expense.name))
+            };
+            ofx.push_str(&format!(
+                "<STMTTRN><TRNTYPE>{}</TRNTYPE><DTPOSTED>{}</DTPOSTED><TRNAMT>{}</TRNAMT><FITID>{}</FITID><NAME>{}</NAME>{memo}</STMTTRN>\n",
+                if amount.amount < 0 { "DEBIT" } else { "CREDIT" },
+                expense.date.format("%Y%m%d"),
+                amount.decimal(),
+                expense.id,
+                escape_xml(&name),
+            ));
+        }
+        ofx.push_str(&format!(
+            "</BANKTRANLIST>\n<LEDGERBAL><BALAMT>{}</BALAMT><DTASOF>{now}</DTASOF></LEDGERBAL>\n</STMTRS></STMTTRNRS>\n",
+            balances[&account_id].decimal(),
+        ));
+    }
+
+    ofx.push_str("</BANKMSGSRSV1>\n</OFX>\n");
+    ofx
+}
@@ -0,0 +1,71 @@
+//! Axum's extractors, but failing with an `AppError` so that bad input gets the same error page
+//! as everything else instead of a plain-text rejection.
+
+use crate::error::AppError;
+use axum::async_trait;
+use axum::extract::rejection::{FormRejection, QueryRejection};
+use axum::extract::{FromRequest, FromRequestParts};
+use axum::http::request::Parts;
+use axum::http::Request;
+
+pub struct Form<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for Form<T>
+where
+    axum::Form<T>: FromRequest<S, B, Rejection = FormRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    type Rejection = AppError;
+
+    async fn from_request(request: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::Form::<T>::from_request(request, state).await {
+            Ok(axum::Form(value)) => Ok(Form(value)),
+            Err(rejection) => Err(AppError::BadRequest(format!(
+                "Niepoprawne dane formularza ({}).",
+                rejection.body_text()
+            ))),
+        }
+    }
+}
+
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    axum::extract::Query<T>: FromRequestParts<S, Rejection = QueryRejection>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Query::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Query(value)) => Ok(Query(value)),
+            Err(rejection) => Err(AppError::BadRequest(format!(
+                "Niepoprawne parametry adresu ({}).",
+                rejection.body_text()
+            ))),
+        }
+    }
+}
+
+/// A path segment which doesn't parse, like `/expense/abc`, can't name anything that exists.
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    axum::extract::Path<T>: FromRequestParts<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::extract::Path::<T>::from_request_parts(parts, state).await {
+            Ok(axum::extract::Path(value)) => Ok(Path(value)),
+            Err(_) => Err(AppError::NotFound),
+        }
+    }
+}
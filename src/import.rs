@@ -0,0 +1,208 @@
+use crate::money::{self, Currency};
+use chrono::NaiveDate;
+
+/// How to read a bank's CSV export. Columns are numbered from 1, like in a spreadsheet.
+#[derive(Clone)]
+pub struct Mapping {
+    pub delimiter: char,
+    /// Number of header lines to skip.
+    pub skip_rows: usize,
+    pub date_column: usize,
+    pub date_format: String,
+    pub amount_column: usize,
+    pub description_column: usize,
+    /// Banks usually export spending as negative amounts, while we store it as positive.
+    pub negate: bool,
+}
+
+impl Default for Mapping {
+    fn default() -> Self {
+        Mapping {
+            delimiter: ';',
+            skip_rows: 1,
+            date_column: 1,
+            date_format: "%Y-%m-%d".to_string(),
+            amount_column: 2,
+            description_column: 3,
+            negate: true,
+        }
+    }
+}
+
+/// A single transaction read from a bank statement.
+pub struct Row {
+    pub date: NaiveDate,
+    /// In minor units, positive for expenses.
+    pub amount: i64,
+    pub description: String,
+}
+
+fn get_column(record: &csv::StringRecord, column: usize) -> Result<&str, String> {
+    column
+        .checked_sub(1)
+        .and_then(|i| record.get(i))
+        .map(str::trim)
+        .ok_or(format!("brak kolumny {}", column))
+}
+
+/// Read all transactions from a CSV file, failing on the first malformed line. Amounts are in
+/// the account's currency.
+pub fn parse_statement(
+    mapping: &Mapping,
+    currency: Currency,
+    data: &[u8],
+) -> Result<Vec<Row>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(mapping.delimiter as u8)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data);
+
+    let mut rows = Vec::new();
+    for (i, record) in reader.records().enumerate().skip(mapping.skip_rows) {
+        let line = i + 1;
+        let record = record.map_err(|e| format!("wiersz {}: {}", line, e))?;
+        if record.iter().all(|field| field.trim().is_empty()) {
+            continue;
+        }
+
+        let date = get_column(&record, mapping.date_column)
+            .and_then(|date| {
+                NaiveDate::parse_from_str(date, &mapping.date_format)
+                    .map_err(|_| format!("niepoprawna data \"{}\"", date))
+            })
+            .map_err(|e| format!("wiersz {}: {}", line, e))?;
+        let amount = get_column(&record, mapping.amount_column)
+            .and_then(|amount| {
+                money::parse_minor_units(amount, currency.minor_digits())
+                    .map_err(|_| format!("niepoprawna kwota \"{}\"", amount))
+            })
+            .map_err(|e| format!("wiersz {}: {}", line, e))?;
+        let description = get_column(&record, mapping.description_column)
+            .map_err(|e| format!("wiersz {}: {}", line, e))?;
+
+        rows.push(Row {
+            date,
+            amount: if mapping.negate { -amount } else { amount },
+            description: description.to_string(),
+        });
+    }
+
+    Ok(rows)
+}
+
+fn words(name: &str) -> Vec<String> {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Whether two descriptions plausibly refer to the same transaction, e.g. "Biedronka" and
+/// "BIEDRONKA 1234 WARSZAWA".
+pub fn is_similar(a: &str, b: &str) -> bool {
+    let (a, b) = (words(a), words(b));
+    if a.is_empty() || b.is_empty() {
+        return a == b;
+    }
+    let (a_joined, b_joined) = (a.join(" "), b.join(" "));
+    a_joined.contains(&b_joined)
+        || b_joined.contains(&a_joined)
+        || a.iter()
+            .any(|word| word.chars().count() >= 4 && b.contains(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date;
+
+    fn summary(rows: &[Row]) -> Vec<(NaiveDate, i64, &str)> {
+        rows.iter()
+            .map(|row| (row.date, row.amount, row.description.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_with_default_mapping() {
+        let data = "Data;Kwota;Opis\n2024-03-01;-21,37;Biedronka\n2024-03-02;1 500,00;Wypłata\n";
+        let rows = parse_statement(&Mapping::default(), Currency::PLN, data.as_bytes()).unwrap();
+        assert_eq!(
+            summary(&rows),
+            vec![
+                (date(2024, 3, 1), 2137, "Biedronka"),
+                (date(2024, 3, 2), -150000, "Wypłata"),
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_mapping() {
+        let mapping = Mapping {
+            delimiter: ',',
+            skip_rows: 2,
+            date_column: 3,
+            date_format: "%d.%m.%Y".to_string(),
+            amount_column: 1,
+            description_column: 2,
+            negate: false,
+        };
+        let data =
+            "Wyciąg\nKwota,Opis,Data\n12.5,\"Kawa, duża\",05.04.2024\n\n-3,Zwrot,06.04.2024\n";
+        let rows = parse_statement(&mapping, Currency::EUR, data.as_bytes()).unwrap();
+        assert_eq!(
+            summary(&rows),
+            vec![
+                (date(2024, 4, 5), 1250, "Kawa, duża"),
+                (date(2024, 4, 6), -300, "Zwrot"),
+            ]
+        );
+    }
+
+    #[test]
+    fn uses_minor_units_of_currency() {
+        let data = "Data;Kwota;Opis\n2024-03-01;-1500;Sushi\n";
+        let rows =
+            parse_statement(&Mapping::default(), "JPY".parse().unwrap(), data.as_bytes()).unwrap();
+        assert_eq!(rows[0].amount, 1500);
+        let data = "Data;Kwota;Opis\n2024-03-01;-15,5;Sushi\n";
+        assert!(
+            parse_statement(&Mapping::default(), "JPY".parse().unwrap(), data.as_bytes()).is_err()
+        );
+    }
+
+    #[test]
+    fn reports_malformed_lines() {
+        let mapping = Mapping::default();
+        let parse = |data: &str| parse_statement(&mapping, Currency::PLN, data.as_bytes());
+        assert_eq!(
+            parse("Data;Kwota;Opis\n2024-03-01;-1;A\n2024-13-01;-1;B\n").err(),
+            Some("wiersz 3: niepoprawna data \"2024-13-01\"".to_string())
+        );
+        assert_eq!(
+            parse("Data;Kwota;Opis\n2024-03-01;abc;A\n").err(),
+            Some("wiersz 2: niepoprawna kwota \"abc\"".to_string())
+        );
+        assert_eq!(
+            parse("Data;Kwota;Opis\n2024-03-01;-1\n").err(),
+            Some("wiersz 2: brak kolumny 3".to_string())
+        );
+    }
+
+    #[test]
+    fn similar_descriptions() {
+        assert!(is_similar("Biedronka", "BIEDRONKA 1234 WARSZAWA"));
+        assert!(is_similar("Opłata za kartę", "opłata za kartę"));
+        assert!(is_similar("Zakupy w Lidlu", "LIDL zakupy"));
+        assert!(is_similar("", ""));
+    }
+
+    #[test]
+    fn dissimilar_descriptions() {
+        // Words shorter than four letters are too common to count.
+        assert!(!is_similar("Bar pod kogutem", "bar mleczny"));
+        assert!(!is_similar("Biedronka", "Żabka"));
+        assert!(!is_similar("Biedronka", ""));
+    }
+}
@@ -1,19 +1,42 @@
-use axum::response::Redirect;
+use axum::extract::{DefaultBodyLimit, Multipart, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Redirect, Response};
 use axum::routing::{get, post};
-use axum::{Form, Router};
-use chrono::NaiveDate;
+use axum::Router;
+use chrono::{Datelike, NaiveDate};
+use error::AppError;
+use extract::{Form, Path, Query};
 use minijinja::render;
+use money::{Currency, Money};
 use rand::seq::SliceRandom;
-use rusqlite::types::ToSqlOutput;
-use rusqlite::{Connection, Result, ToSql};
+use rates::ExchangeRates;
+use recurring::{Recurring, Rule};
+use rusqlite::{OptionalExtension, Result};
+use serde::ser::SerializeStruct;
 use serde::{Deserialize, Serialize, Serializer};
 use std::cmp::Reverse;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::{TryFrom, TryInto};
 use std::fmt::{Display, Formatter};
 use std::hash::Hash;
 use std::net::SocketAddr;
+use std::os::unix::fs::FileTypeExt;
+use std::pin::Pin;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+
+mod api;
+mod charts;
+mod config;
+mod db;
+mod error;
+mod export;
+mod extract;
+mod import;
+mod migrations;
+mod money;
+mod rates;
+mod recurring;
 
 const QUOTES: [&str; 17] = [
     "Bardziej od pieniędzy, potrzebujesz miłości. Miłość to siła nabywcza szczęścia.",
@@ -51,7 +74,7 @@ const HTML_HEADER: &str = r#"<!DOCTYPE html>
     <header>
         <h1>Casa</h1>
         <p>
-            <a href="/">Casa</a> | <a href="/own">Przelew własny</a> | <a href="/stats">Podsumowanie</a>
+            <a href="/">Casa</a> | <a href="/own">Przelew własny</a> | <a href="/search">Szukaj</a> | <a href="/stats">Podsumowanie</a> | <a href="/accounts">Konta</a> | <a href="/categories">Kategorie</a> | <a href="/budgets">Budżety</a> | <a href="/recurring">Cykliczne</a> | <a href="/import">Import</a> | <a href="/export">Eksport</a>
         </p>
     </header>
 "#;
@@ -77,6 +100,12 @@ static COUNTER: AtomicU64 = AtomicU64::new(0);
 
 type SqliteInteger = i32;
 
+/// `NaiveDate` shorthand for tests.
+#[cfg(test)]
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
 fn get_month_name(a: u16) -> &'static str {
     match a {
         1 => "styczeń",
@@ -95,37 +124,7 @@ fn get_month_name(a: u16) -> &'static str {
     }
 }
 
-#[allow(clippy::upper_case_acronyms)]
-#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
-enum Currency {
-    // Remember to update `try_from` when adding new currencies.
-    PLN,
-    EUR,
-    USD,
-    GBP,
-}
-
-impl ToSql for Currency {
-    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
-        Ok(ToSqlOutput::from(format!("{:?}", self))) // TODO: don't abuse debug formatting
-    }
-}
-
-impl TryFrom<String> for Currency {
-    type Error = ();
-
-    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
-        match value.as_str() {
-            "PLN" => Ok(Currency::PLN),
-            "EUR" => Ok(Currency::EUR),
-            "USD" => Ok(Currency::USD),
-            "GBP" => Ok(Currency::GBP),
-            _ => Err(()),
-        }
-    }
-}
-
-#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 struct SpecificMonth {
     year: u16,
     month: u16,
@@ -141,20 +140,61 @@ impl Serialize for SpecificMonth {
     }
 }
 
-impl TryFrom<String> for SpecificMonth {
-    type Error = ();
+impl From<NaiveDate> for SpecificMonth {
+    fn from(date: NaiveDate) -> Self {
+        SpecificMonth {
+            year: date.year() as u16,
+            month: date.month() as u16,
+        }
+    }
+}
 
-    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
-        // Expected format: "2022-12" or "2022-12-whatever".
-        let parts: Vec<_> = value.split('-').collect();
+impl SpecificMonth {
+    fn first_day(self) -> NaiveDate {
+        NaiveDate::from_ymd_opt(self.year.into(), self.month.into(), 1).unwrap()
+    }
 
-        #[allow(clippy::get_first)]
-        let (year_str, month_str) = (parts.get(0).ok_or(())?, parts.get(1).ok_or(())?);
+    fn last_day(self) -> NaiveDate {
+        self.next().first_day().pred_opt().unwrap()
+    }
 
-        Ok(SpecificMonth {
-            year: year_str.parse().unwrap(),
-            month: month_str.parse().unwrap(),
-        })
+    /// The month in URLs, e.g. "2024-03".
+    fn param(self) -> String {
+        format!("{:04}-{:02}", self.year, self.month)
+    }
+
+    fn previous(self) -> Self {
+        match self.month {
+            1 => SpecificMonth {
+                year: self.year - 1,
+                month: 12,
+            },
+            month => SpecificMonth {
+                year: self.year,
+                month: month - 1,
+            },
+        }
+    }
+
+    fn next(self) -> Self {
+        match self.month {
+            12 => SpecificMonth {
+                year: self.year + 1,
+                month: 1,
+            },
+            month => SpecificMonth {
+                year: self.year,
+                month: month + 1,
+            },
+        }
+    }
+}
+
+impl std::str::FromStr for SpecificMonth {
+    type Err = chrono::ParseError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        NaiveDate::parse_from_str(&format!("{s}-01"), "%Y-%m-%d").map(SpecificMonth::from)
     }
 }
 
@@ -165,10 +205,14 @@ impl Display for SpecificMonth {
 }
 
 struct Expense {
+    id: SqliteInteger,
     name: String,
-    value: f64,
-    date: SpecificMonth,
-    currency: Currency,
+    value: Money,
+    date: NaiveDate,
+    account_id: SqliteInteger,
+    category_id: Option<SqliteInteger>,
+    /// Set for both sides of an own transfer, see `Transfer`.
+    transfer_id: Option<SqliteInteger>,
 }
 
 impl Serialize for Expense {
@@ -176,29 +220,22 @@ impl Serialize for Expense {
     where
         S: Serializer,
     {
-        // We use `Serialize` only for templating, let's reuse `Display`.
-        serializer.serialize_str(format!("{}", self).as_str())
+        // We use `Serialize` only for templating, let's reuse `Display` and add the ID for links.
+        let mut state = serializer.serialize_struct("Expense", 2)?;
+        state.serialize_field("id", &self.id)?;
+        state.serialize_field("text", &format!("{}", self))?;
+        state.end()
     }
 }
 
 impl Display for Expense {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let income = self.value < 0.0;
-        write!(
-            f,
-            "{} ({})",
-            self.name,
-            match (income, &self.currency) {
-                (false, Currency::PLN) => format!("{} zł", self.value),
-                (true, Currency::PLN) => format!("+{} zł", -self.value),
-                (false, Currency::EUR) => format!("€{}", self.value),
-                (true, Currency::EUR) => format!("+€{}", -self.value),
-                (false, Currency::USD) => format!("${}", self.value),
-                (true, Currency::USD) => format!("+${}", -self.value),
-                (false, Currency::GBP) => format!("£{}", self.value),
-                (true, Currency::GBP) => format!("+£{}", -self.value),
-            },
-        )
+        // Expenses are positive, incomes are negative.
+        if self.value.amount < 0 {
+            write!(f, "{} (+{})", self.name, -self.value)
+        } else {
+            write!(f, "{} ({})", self.name, self.value)
+        }
     }
 }
 
@@ -209,331 +246,3596 @@ impl std::fmt::Debug for Expense {
     }
 }
 
+/// Money moved between own accounts: an expense on the sending account and an income on the
+/// receiving one, which are added, edited and deleted together.
+struct Transfer {
+    id: SqliteInteger,
+    from: Expense,
+    to: Expense,
+}
+
+impl Display for Transfer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.from.value.currency == self.to.value.currency {
+            write!(f, "{} ({})", self.from.name, self.from.value)
+        } else {
+            write!(
+                f,
+                "{} ({} → {})",
+                self.from.name, self.from.value, -self.to.value
+            )
+        }
+    }
+}
+
+/// A transfer as entered in the form. Both values are positive.
+struct NewTransfer {
+    name: String,
+    date: NaiveDate,
+    from_account_id: SqliteInteger,
+    from: Money,
+    to_account_id: SqliteInteger,
+    to: Money,
+}
+
 #[derive(Serialize)]
 struct Account {
     name: String,
     currency: Currency,
+    display_order: i64,
+    /// Hidden from the forms adding new entries, but kept with its history.
+    archived: bool,
+    /// Money on the account before the first tracked expense.
+    opening_balance: Money,
+}
+
+/// Ids of the given accounts in the order chosen on the accounts page. Templates iterate over
+/// maps by key, so they need this to list accounts in that order.
+fn account_order(accounts: &HashMap<SqliteInteger, Account>) -> Vec<SqliteInteger> {
+    let mut ids = accounts.keys().copied().collect::<Vec<_>>();
+    ids.sort_by_key(|id| (accounts[id].display_order, *id));
+    ids
+}
+
+/// Currencies of the given accounts, for filters and forms.
+fn account_currencies(accounts: &HashMap<SqliteInteger, Account>) -> Vec<Currency> {
+    let mut currencies = accounts
+        .values()
+        .map(|account| account.currency)
+        .collect::<Vec<_>>();
+    currencies.sort();
+    currencies.dedup();
+    currencies
+}
+
+/// How many decimals to allow in a form's amount. Without a valid account there's no currency
+/// yet, so the amount is checked as if it had cents.
+fn minor_digits(account: Option<&Account>) -> u32 {
+    account.map_or(2, |account| account.currency.minor_digits())
+}
+
+#[derive(Serialize)]
+struct Category {
+    name: String,
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum BudgetTarget {
+    Category(SqliteInteger),
+    Account(SqliteInteger),
+}
+
+impl TryFrom<String> for BudgetTarget {
+    type Error = ();
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        // Expected format: "category-1" or "account-1".
+        let (kind, id) = value.split_once('-').ok_or(())?;
+        let id = id.parse().map_err(|_| ())?;
+        match kind {
+            "category" => Ok(BudgetTarget::Category(id)),
+            "account" => Ok(BudgetTarget::Account(id)),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for BudgetTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BudgetTarget::Category(id) => write!(f, "category-{}", id),
+            BudgetTarget::Account(id) => write!(f, "account-{}", id),
+        }
+    }
+}
+
+impl BudgetTarget {
+    fn name(
+        &self,
+        accounts: &HashMap<SqliteInteger, Account>,
+        categories: &HashMap<SqliteInteger, Category>,
+    ) -> String {
+        match self {
+            BudgetTarget::Category(id) => categories[id].name.clone(),
+            BudgetTarget::Account(id) => {
+                format!("[{}] {}", accounts[id].currency, accounts[id].name)
+            }
+        }
+    }
+
+    fn matches(&self, expense: &Expense) -> bool {
+        match self {
+            BudgetTarget::Category(id) => expense.category_id == Some(*id),
+            BudgetTarget::Account(id) => expense.account_id == *id,
+        }
+    }
+}
+
+/// A monthly spending limit.
+struct Budget {
+    id: SqliteInteger,
+    target: BudgetTarget,
+    limit: Money,
+}
+
+#[derive(Serialize)]
+struct BudgetStatus {
+    name: String,
+    limit: Money,
+    spent: Money,
+    left: Money,
+    over: Money,
+    exceeded: bool,
+}
+
+#[derive(Serialize)]
+struct CategoryTotal {
+    name: String,
+    totals: Vec<Money>,
+    /// All of `totals` in the reporting currency.
+    total: Money,
+}
+
+/// Spending and income over some time, both positive.
+#[derive(Serialize)]
+struct Totals {
+    spent: Money,
+    income: Money,
+    /// Income minus spending.
+    net: Money,
+}
+
+/// A month's spending and income, leaving out transfers between own accounts.
+#[derive(Serialize)]
+struct MonthTotals {
+    /// Per currency, sorted by it.
+    per_currency: Vec<Totals>,
+    /// All of `per_currency` in the reporting currency, unless it's the only one anyway.
+    converted: Option<Totals>,
+    /// Currencies left out of `converted`, as there are no rates for them.
+    unknown_rates: Vec<Currency>,
+}
+
+impl MonthTotals {
+    fn in_reporting_currency(&self) -> &Totals {
+        // Without `converted`, the reporting currency is the only one.
+        self.converted.as_ref().unwrap_or(&self.per_currency[0])
+    }
 }
 
 trait Repository {
-    fn add(&self, name: String, value: f64, date: NaiveDate, account_id: String);
-    fn list(&self) -> Vec<Expense>;
-    fn balance(&self) -> HashMap<Currency, i64>;
-    fn get_notepad(&self) -> String;
-    fn to_eur_approx(&self, currency: Currency) -> f64;
-    fn get_accounts(&self) -> HashMap<SqliteInteger, Account>;
-    fn get_balance_per_account(&self) -> HashMap<String, i64>;
+    fn add(
+        &self,
+        name: String,
+        value: Money,
+        date: NaiveDate,
+        account_id: String,
+        category_id: Option<SqliteInteger>,
+    ) -> Result<SqliteInteger>;
+    /// Adds imported rows (name, amount, date) to the account in one transaction, so that an
+    /// import which fails halfway adds nothing.
+    fn add_imported(
+        &self,
+        account_id: SqliteInteger,
+        rows: Vec<(String, Money, NaiveDate)>,
+    ) -> Result<()>;
+    /// The date of the oldest expense, if there are any.
+    fn get_first_date(&self) -> Result<Option<NaiveDate>>;
+    /// The newest `limit` months with any expenses, only those before `before` if given.
+    fn get_months(&self, before: Option<SpecificMonth>, limit: usize)
+        -> Result<Vec<SpecificMonth>>;
+    /// Expenses from the `oldest` month through the `newest` one, newest first.
+    fn list_months(&self, oldest: SpecificMonth, newest: SpecificMonth) -> Result<Vec<Expense>>;
+    /// Oldest first, in the order they changed the account's balance.
+    fn get_account_expenses(&self, account_id: SqliteInteger) -> Result<Vec<Expense>>;
+    fn get(&self, id: SqliteInteger) -> Result<Option<Expense>>;
+    /// Expenses matching `search`, newest first, at most `limit` of them (if given) after
+    /// skipping `offset`.
+    fn find(&self, search: &Search, limit: Option<usize>, offset: usize) -> Result<Vec<Expense>>;
+    /// How many expenses match `search`.
+    fn count(&self, search: &Search) -> Result<usize>;
+    /// Sums of the expenses matching `search` per day and currency, to convert them.
+    fn sum_per_day(&self, search: &Search) -> Result<Vec<(NaiveDate, Money)>>;
+    fn update(
+        &self,
+        id: SqliteInteger,
+        name: String,
+        value: Money,
+        date: NaiveDate,
+        account_id: String,
+        category_id: Option<SqliteInteger>,
+    ) -> Result<()>;
+    fn delete(&self, id: SqliteInteger) -> Result<()>;
+    fn balance(&self) -> Result<HashMap<Currency, Money>>;
+    fn get_notepad(&self) -> Result<String>;
+    fn get_exchange_rates(&self) -> Result<ExchangeRates>;
+    /// `amount` in `to` at the rates valid on `date`. To convert many amounts, get all rates
+    /// with `get_exchange_rates` once instead.
+    fn convert(&self, amount: Money, to: Currency, date: NaiveDate) -> Result<Option<Money>>;
+    /// The currency of the totals on the stats page, EUR unless chosen otherwise.
+    fn get_reporting_currency(&self) -> Result<Currency>;
+    fn set_reporting_currency(&self, currency: Currency) -> Result<()>;
+    /// Adds rates to the history, replacing those already stored for the same day.
+    fn add_exchange_rates(&self, rates: &[(Currency, NaiveDate, f64)]) -> Result<()>;
+    fn get_accounts(&self) -> Result<HashMap<SqliteInteger, Account>>;
+    /// The new account's currency is the one of `opening_balance`. It comes last in the order.
+    fn add_account(&self, name: String, opening_balance: Money) -> Result<SqliteInteger>;
+    /// The currency of an account can't change, as it would change the meaning of its amounts.
+    fn update_account(
+        &self,
+        id: SqliteInteger,
+        name: String,
+        opening_balance: Money,
+        archived: bool,
+    ) -> Result<()>;
+    /// Swaps the account with the previous (`up`) or the next one in the order.
+    fn move_account(&self, id: SqliteInteger, up: bool) -> Result<()>;
+    fn get_categories(&self) -> Result<HashMap<SqliteInteger, Category>>;
+    fn add_category(&self, name: String) -> Result<()>;
+    fn get_category_totals(
+        &self,
+        currency: Currency,
+    ) -> Result<Vec<(SpecificMonth, Vec<CategoryTotal>)>>;
+    /// Totals of the months from `oldest` through `newest` which have any expenses.
+    fn get_monthly_totals(
+        &self,
+        oldest: SpecificMonth,
+        newest: SpecificMonth,
+        currency: Currency,
+    ) -> Result<HashMap<SpecificMonth, MonthTotals>>;
+    fn get_budgets(&self) -> Result<Vec<Budget>>;
+    fn set_budget(&self, target: BudgetTarget, limit: Money) -> Result<()>;
+    fn delete_budget(&self, id: SqliteInteger) -> Result<()>;
+    fn get_recurring(&self) -> Result<Vec<Recurring>>;
+    /// Ignores `id` and `last_date` of the given template.
+    fn add_recurring(&self, recurring: &Recurring) -> Result<()>;
+    fn delete_recurring(&self, id: SqliteInteger) -> Result<()>;
+    /// Add all recurring entries due by `today`, returning how many were added.
+    fn materialize_recurring(&self, today: NaiveDate) -> Result<usize>;
+    fn get_import_mapping(&self, account_id: SqliteInteger) -> Result<import::Mapping>;
+    fn set_import_mapping(
+        &self,
+        account_id: SqliteInteger,
+        mapping: &import::Mapping,
+    ) -> Result<()>;
+    /// An existing expense on the same account and date, with the same amount and a similar name.
+    fn find_duplicate(
+        &self,
+        account_id: SqliteInteger,
+        date: NaiveDate,
+        amount: i64,
+        name: &str,
+    ) -> Result<Option<Expense>>;
+    fn get_balance_per_account(&self) -> Result<HashMap<SqliteInteger, Money>>;
+    /// Balances of all accounts at the end of each month from `oldest` through `newest`.
+    fn get_balance_history(
+        &self,
+        oldest: SpecificMonth,
+        newest: SpecificMonth,
+    ) -> Result<Vec<(SpecificMonth, HashMap<SqliteInteger, Money>)>>;
+    /// Both sides are added in one transaction, so there are no half transfers.
+    fn add_transfer(&self, transfer: &NewTransfer) -> Result<SqliteInteger>;
+    fn get_transfer(&self, id: SqliteInteger) -> Result<Option<Transfer>>;
+    /// Transfers sent from the `oldest` month through the `newest` one, by ID.
+    fn get_transfers(
+        &self,
+        oldest: SpecificMonth,
+        newest: SpecificMonth,
+    ) -> Result<HashMap<SqliteInteger, Transfer>>;
+    fn update_transfer(&self, id: SqliteInteger, transfer: &NewTransfer) -> Result<()>;
+    fn delete_transfer(&self, id: SqliteInteger) -> Result<()>;
+}
+
+const EXPENSE_SELECT: &str = "select expenses.id, expenses.name, expenses.amount, date(expenses.date), expenses.account_id, accounts.currency, expenses.category_id, transfers.id from expenses join accounts on account_id = accounts.id left join transfers on transfers.from_expense_id = expenses.id or transfers.to_expense_id = expenses.id";
+
+fn expense_from_row(row: &rusqlite::Row) -> Result<Expense> {
+    Ok(Expense {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        value: Money::new(row.get(2)?, row.get(5)?),
+        date: row.get(3)?,
+        account_id: row.get(4)?,
+        category_id: row.get(6)?,
+        transfer_id: row.get(7)?,
+    })
 }
 
 struct SQLiteRepository {
-    connection: Connection,
+    connection: r2d2::PooledConnection<db::ConnectionManager>,
 }
 
 impl Repository for SQLiteRepository {
-    fn add(&self, name: String, value: f64, date: NaiveDate, account_id: String) {
+    fn add(
+        &self,
+        name: String,
+        value: Money,
+        date: NaiveDate,
+        account_id: String,
+        category_id: Option<SqliteInteger>,
+    ) -> Result<SqliteInteger> {
+        // Selecting from `accounts` makes sure the amount is in the account's currency.
+        let inserted = self.connection.execute(
+            "insert into expenses (name, amount, date, account_id, category_id) select ?1, ?2, ?3, id, ?6 from accounts where id = ?4 and currency = ?5",
+            (
+                name,
+                value.amount,
+                date.format("%Y-%m-%d").to_string(),
+                account_id,
+                value.currency,
+                category_id,
+            ),
+        )?;
+        if inserted != 1 {
+            // No account with a matching currency.
+            return Err(rusqlite::Error::StatementChangedRows(inserted));
+        }
+        Ok(self.connection.last_insert_rowid() as SqliteInteger)
+    }
+
+    fn add_imported(
+        &self,
+        account_id: SqliteInteger,
+        rows: Vec<(String, Money, NaiveDate)>,
+    ) -> Result<()> {
+        // `add` uses the same connection, so it's part of the transaction.
+        let tx = self.connection.unchecked_transaction()?;
+        for (name, value, date) in rows {
+            self.add(name, value, date, account_id.to_string(), None)?;
+        }
+        tx.commit()
+    }
+
+    fn get_first_date(&self) -> Result<Option<NaiveDate>> {
+        self.connection
+            .query_row("select date(min(date)) from expenses", [], |row| row.get(0))
+    }
+
+    fn get_months(
+        &self,
+        before: Option<SpecificMonth>,
+        limit: usize,
+    ) -> Result<Vec<SpecificMonth>> {
+        let mut statement = self.connection.prepare(
+            "select distinct strftime('%Y-%m', date) from expenses where ?1 is null or date < ?1 order by 1 desc limit ?2",
+        )?;
+        let months = statement
+            .query_map(
+                (before.map(|before| before.first_day()), limit as i64),
+                |row| {
+                    row.get::<usize, String>(0)?.parse().map_err(|e| {
+                        rusqlite::Error::FromSqlConversionFailure(
+                            0,
+                            rusqlite::types::Type::Text,
+                            Box::new(e),
+                        )
+                    })
+                },
+            )?
+            .collect();
+        months
+    }
+
+    fn list_months(&self, oldest: SpecificMonth, newest: SpecificMonth) -> Result<Vec<Expense>> {
+        let mut statement = self.connection.prepare(&format!(
+            "{EXPENSE_SELECT} where expenses.date >= ?1 and expenses.date < ?2 order by date desc, expenses.id desc"
+        ))?;
+        let expenses = statement
+            .query_map(
+                (oldest.first_day(), newest.next().first_day()),
+                expense_from_row,
+            )?
+            .collect();
+        expenses
+    }
+
+    fn get_account_expenses(&self, account_id: SqliteInteger) -> Result<Vec<Expense>> {
+        let mut statement = self.connection.prepare(&format!(
+            "{EXPENSE_SELECT} where expenses.account_id = ?1 order by date, expenses.id"
+        ))?;
+        let expenses = statement
+            .query_map([account_id], expense_from_row)?
+            .collect();
+        expenses
+    }
+
+    fn get(&self, id: SqliteInteger) -> Result<Option<Expense>> {
         self.connection
-            .execute(
-                "insert into expenses (name, value, date, account_id) values (?1, ?2, ?3, ?4)",
-                (name, value, date.format("%Y-%m-%d").to_string(), account_id),
+            .query_row(
+                &format!("{EXPENSE_SELECT} where expenses.id = ?1"),
+                [id],
+                expense_from_row,
             )
-            .unwrap();
+            .optional()
     }
 
-    fn list(&self) -> Vec<Expense> {
-        let mut expenses: Vec<Expense> = Vec::new();
-        let mut statement = self.connection.prepare("select expenses.name, cast(expenses.value as real), expenses.date, accounts.currency from expenses join accounts on account_id = accounts.id order by date desc, expenses.rowid desc").unwrap();
-        let expenses_iter = statement
-            .query_map([], |row| {
-                Ok(Expense {
-                    name: row.get(0)?,
-                    value: row.get(1)?,
-                    date: row.get::<usize, String>(2)?.try_into().unwrap(),
-                    currency: row.get::<usize, String>(3).unwrap().try_into().unwrap(),
-                })
-            })
-            .unwrap();
-        for expense in expenses_iter {
-            expenses.push(expense.unwrap());
+    fn find(&self, search: &Search, limit: Option<usize>, offset: usize) -> Result<Vec<Expense>> {
+        let (condition, mut params) = self.search_condition(search)?;
+        // SQLite takes a negative limit as no limit.
+        params.push(Box::new(limit.map_or(-1, |limit| limit as i64)));
+        params.push(Box::new(offset as i64));
+        let mut statement = self.connection.prepare(&format!(
+            "{EXPENSE_SELECT} {condition} order by date desc, expenses.id desc limit ? offset ?"
+        ))?;
+        let expenses = statement
+            .query_map(rusqlite::params_from_iter(params), expense_from_row)?
+            .collect();
+        expenses
+    }
+
+    fn count(&self, search: &Search) -> Result<usize> {
+        let (condition, params) = self.search_condition(search)?;
+        self.connection.query_row(
+            &format!("select count(*) from expenses join accounts on expenses.account_id = accounts.id {condition}"),
+            rusqlite::params_from_iter(params),
+            |row| row.get(0),
+        )
+    }
+
+    fn sum_per_day(&self, search: &Search) -> Result<Vec<(NaiveDate, Money)>> {
+        let (condition, params) = self.search_condition(search)?;
+        let mut statement = self.connection.prepare(&format!(
+            "select date(expenses.date) as day, accounts.currency, sum(expenses.amount) from expenses join accounts on expenses.account_id = accounts.id {condition} group by day, accounts.currency"
+        ))?;
+        let sums = statement
+            .query_map(rusqlite::params_from_iter(params), |row| {
+                Ok((row.get(0)?, Money::new(row.get(2)?, row.get(1)?)))
+            })?
+            .collect();
+        sums
+    }
+
+    fn update(
+        &self,
+        id: SqliteInteger,
+        name: String,
+        value: Money,
+        date: NaiveDate,
+        account_id: String,
+        category_id: Option<SqliteInteger>,
+    ) -> Result<()> {
+        let updated = self.connection.execute(
+            "update expenses set name = ?1, amount = ?2, date = ?3, account_id = ?4, category_id = ?7 where id = ?5 and exists (select 1 from accounts where id = ?4 and currency = ?6)",
+            (
+                name,
+                value.amount,
+                date.format("%Y-%m-%d").to_string(),
+                account_id,
+                id,
+                value.currency,
+                category_id,
+            ),
+        )?;
+        if updated != 1 {
+            // No such expense or no account with a matching currency.
+            return Err(rusqlite::Error::StatementChangedRows(updated));
         }
+        Ok(())
+    }
 
-        expenses
+    fn delete(&self, id: SqliteInteger) -> Result<()> {
+        self.connection
+            .execute("delete from expenses where id = ?1", [id])?;
+        Ok(())
     }
 
-    fn balance(&self) -> HashMap<Currency, i64> {
+    fn balance(&self) -> Result<HashMap<Currency, Money>> {
         let mut map = HashMap::new();
         let mut p = self
             .connection
-            .prepare("select accounts.currency, -sum(expenses.value) from expenses join accounts on expenses.account_id = accounts.id group by accounts.currency")
-            .unwrap();
+            .prepare("select currency, sum(balance) from (select accounts.currency, accounts.opening_balance - coalesce(sum(expenses.amount), 0) as balance from accounts left join expenses on expenses.account_id = accounts.id group by accounts.id) group by currency")?;
 
-        let balance_iter = p
-            .query_map([], |row| {
-                let currency: String = row.get(0)?;
-                let value: f64 = row.get(1)?;
-                Ok((currency, value))
-            })
-            .unwrap();
+        let balance_iter = p.query_map([], |row| {
+            let currency: Currency = row.get(0)?;
+            let amount: i64 = row.get(1)?;
+            Ok((currency, amount))
+        })?;
         for bal in balance_iter {
-            let (currency, value) = bal.unwrap();
-            map.insert(currency.try_into().unwrap(), value.floor() as i64);
+            let (currency, amount) = bal?;
+            map.insert(currency, Money::new(amount, currency));
         }
-        map
+        Ok(map)
     }
 
-    fn get_notepad(&self) -> String {
-        self.connection
+    fn get_notepad(&self) -> Result<String> {
+        let notepad = self
+            .connection
             .query_row(
                 "select value from key_value_store where key = 'notepad'",
                 [],
                 |row| row.get(0),
             )
-            .unwrap_or("".to_string())
+            .optional()?;
+        Ok(notepad.unwrap_or_default())
     }
 
-    fn to_eur_approx(&self, currency: Currency) -> f64 {
-        // TODO: query the database once per web page visit and get all currencies.
-        let rate: Result<f64, _> = self.connection.query_row(
-            "select rate from exchange_rates where currency = ?1",
-            // TODO: don't abuse `Debug`.
-            [format!("{:?}", currency)],
-            |row| row.get(0),
-        );
-        match rate {
-            Ok(r) => r,
-            Err(e) => {
-                // Fallback to hardcoded exchange rates.
-                eprintln!("Can't get exchange rates for {currency:?}: {e}");
-                match currency {
-                    Currency::PLN => 0.23,
-                    Currency::EUR => 1.00,
-                    Currency::USD => 0.92,
-                    Currency::GBP => 1.18,
-                }
-            }
+    fn get_exchange_rates(&self) -> Result<ExchangeRates> {
+        let mut statement = self
+            .connection
+            .prepare("select currency, date, rate from exchange_rates")?;
+        let rates = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ExchangeRates::new(rates))
+    }
+
+    fn convert(&self, amount: Money, to: Currency, date: NaiveDate) -> Result<Option<Money>> {
+        let mut statement = self.connection.prepare(
+            "select currency, date, rate from exchange_rates where currency in (?1, ?2)",
+        )?;
+        let rates = statement
+            .query_map((amount.currency, to), |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ExchangeRates::new(rates).convert(amount, to, date))
+    }
+
+    fn get_reporting_currency(&self) -> Result<Currency> {
+        let currency = self
+            .connection
+            .query_row(
+                "select value from key_value_store where key = 'reporting_currency'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(currency.unwrap_or(Currency::EUR))
+    }
+
+    fn set_reporting_currency(&self, currency: Currency) -> Result<()> {
+        self.connection.execute(
+            "insert or replace into key_value_store (key, value) values ('reporting_currency', ?1)",
+            [currency],
+        )?;
+        Ok(())
+    }
+
+    fn add_exchange_rates(&self, rates: &[(Currency, NaiveDate, f64)]) -> Result<()> {
+        let tx = self.connection.unchecked_transaction()?;
+        let mut statement = tx.prepare(
+            "insert or replace into exchange_rates (currency, date, rate) values (?1, ?2, ?3)",
+        )?;
+        for (currency, date, rate) in rates {
+            statement.execute((currency, date, rate))?;
         }
+        drop(statement);
+        tx.commit()
     }
 
-    fn get_accounts(&self) -> HashMap<SqliteInteger, Account> {
-        let mut id2account = HashMap::new();
+    fn get_accounts(&self) -> Result<HashMap<SqliteInteger, Account>> {
         let mut statement = self
             .connection
-            .prepare("select id, name, currency from accounts order by display_order")
-            .unwrap();
-        let rows = statement
+            .prepare("select id, name, currency, display_order, archived, opening_balance from accounts order by display_order")?;
+        let id2account = statement
             .query_map([], |row| {
                 Ok((
                     row.get::<usize, SqliteInteger>(0)?,
                     Account {
                         name: row.get(1)?,
-                        currency: row.get::<usize, String>(2).unwrap().try_into().unwrap(),
+                        currency: row.get(2)?,
+                        display_order: row.get(3)?,
+                        archived: row.get(4)?,
+                        opening_balance: Money::new(row.get(5)?, row.get(2)?),
                     },
                 ))
-            })
-            .unwrap();
-        for row in rows {
-            let (id, account) = row.unwrap();
-            id2account.insert(id, account);
-        }
+            })?
+            .collect();
         id2account
     }
 
-    fn get_balance_per_account(&self) -> HashMap<String, i64> {
-        let mut name2balance = HashMap::new();
-        // TODO: extract formatting to Rust.
-        let mut statement = self.connection.prepare("select '[' || accounts.currency || '] ' || accounts.name, cast(-sum(expenses.value) as int) from expenses join accounts on expenses.account_id = accounts.id group by accounts.id").unwrap();
-        let rows = statement
-            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
-            .unwrap();
+    fn add_account(&self, name: String, opening_balance: Money) -> Result<SqliteInteger> {
+        self.connection.execute(
+            "insert into accounts (name, currency, opening_balance, display_order) select ?1, ?2, ?3, coalesce(max(display_order), 0) + 1 from accounts",
+            (name, opening_balance.currency, opening_balance.amount),
+        )?;
+        Ok(self.connection.last_insert_rowid() as SqliteInteger)
+    }
+
+    fn update_account(
+        &self,
+        id: SqliteInteger,
+        name: String,
+        opening_balance: Money,
+        archived: bool,
+    ) -> Result<()> {
+        self.connection.execute(
+            "update accounts set name = ?1, opening_balance = ?2, archived = ?3 where id = ?4 and currency = ?5",
+            (
+                name,
+                opening_balance.amount,
+                archived,
+                id,
+                opening_balance.currency,
+            ),
+        )?;
+        Ok(())
+    }
+
+    fn move_account(&self, id: SqliteInteger, up: bool) -> Result<()> {
+        // Accounts added by hand may share `display_order`, so renumber all of them.
+        let tx = self.write_transaction()?;
+        let mut ids = account_order(&self.get_accounts()?);
+        if let Some(i) = ids.iter().position(|other| *other == id) {
+            match up {
+                true if i > 0 => ids.swap(i - 1, i),
+                false if i + 1 < ids.len() => ids.swap(i, i + 1),
+                _ => {}
+            }
+        }
+        for (display_order, id) in ids.iter().enumerate() {
+            tx.execute(
+                "update accounts set display_order = ?1 where id = ?2",
+                (display_order as i64, id),
+            )?;
+        }
+        tx.commit()
+    }
+
+    fn get_categories(&self) -> Result<HashMap<SqliteInteger, Category>> {
+        let mut statement = self
+            .connection
+            .prepare("select id, name from categories order by display_order")?;
+        let id2category = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<usize, SqliteInteger>(0)?,
+                    Category { name: row.get(1)? },
+                ))
+            })?
+            .collect();
+        id2category
+    }
+
+    fn add_category(&self, name: String) -> Result<()> {
+        self.connection.execute(
+            "insert into categories (name, display_order) select ?1, coalesce(max(display_order), 0) + 1 from categories",
+            [name],
+        )?;
+        Ok(())
+    }
+
+    fn get_category_totals(
+        &self,
+        currency: Currency,
+    ) -> Result<Vec<(SpecificMonth, Vec<CategoryTotal>)>> {
+        let rates = self.get_exchange_rates()?;
+        // Each day is summed separately, to be converted at the rate valid on that day.
+        let mut statement = self.connection.prepare("select date(expenses.date, 'start of month') as month, categories.name, accounts.currency, sum(expenses.amount), date(expenses.date) as day from expenses join accounts on expenses.account_id = accounts.id join categories on expenses.category_id = categories.id group by day, categories.id, accounts.currency order by month desc, categories.display_order, categories.id, accounts.currency")?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<usize, NaiveDate>(0)?,
+                row.get::<usize, String>(1)?,
+                Money::new(row.get(3)?, row.get(2)?),
+                row.get::<usize, NaiveDate>(4)?,
+            ))
+        })?;
+
+        // Rows are sorted, so consecutive rows with the same month and category belong together.
+        let mut months: Vec<(SpecificMonth, Vec<CategoryTotal>)> = Vec::new();
         for row in rows {
-            let (name, balance) = row.unwrap();
-            name2balance.insert(name, balance);
+            let (date, name, total, day) = row?;
+            let month = SpecificMonth::from(date);
+            if months.last().map(|m| m.0) != Some(month) {
+                months.push((month, Vec::new()));
+            }
+            let categories = &mut months.last_mut().unwrap().1;
+            if categories.last().map(|c| &c.name) != Some(&name) {
+                categories.push(CategoryTotal {
+                    name,
+                    totals: Vec::new(),
+                    total: Money::new(0, currency),
+                });
+            }
+            let category = categories.last_mut().unwrap();
+            if let Some(converted) = rates.convert(total, currency, day) {
+                category.total.amount += converted.amount;
+            }
+            match category.totals.last_mut() {
+                Some(last) if last.currency == total.currency => last.amount += total.amount,
+                _ => category.totals.push(total),
+            }
+        }
+
+        for (_, categories) in months.iter_mut() {
+            for category in categories.iter_mut() {
+                category.total = category.total.rounded();
+            }
         }
 
-        name2balance
+        Ok(months)
     }
-}
 
-fn get_grouped_expenses(repo: &SQLiteRepository) -> Vec<(SpecificMonth, Vec<Expense>)> {
-    let mut grouped_expenses: HashMap<SpecificMonth, Vec<Expense>> = HashMap::new();
-    for expense in repo.list() {
-        let month = expense.date;
-        if let Some(v) = grouped_expenses.get_mut(&month) {
-            v.push(expense);
-        } else {
-            grouped_expenses.insert(month, vec![expense]);
+    fn get_monthly_totals(
+        &self,
+        oldest: SpecificMonth,
+        newest: SpecificMonth,
+        currency: Currency,
+    ) -> Result<HashMap<SpecificMonth, MonthTotals>> {
+        let rates = self.get_exchange_rates()?;
+        // Each day is summed separately, to be converted at the rate valid on that day.
+        let mut statement = self.connection.prepare("select date(expenses.date) as day, accounts.currency, sum(max(expenses.amount, 0)), sum(max(-expenses.amount, 0)) from expenses join accounts on expenses.account_id = accounts.id left join transfers on transfers.from_expense_id = expenses.id or transfers.to_expense_id = expenses.id where transfers.id is null and expenses.date >= ?1 and expenses.date < ?2 group by day, accounts.currency")?;
+        let rows = statement.query_map((oldest.first_day(), newest.next().first_day()), |row| {
+            Ok((
+                row.get::<usize, NaiveDate>(0)?,
+                row.get::<usize, Currency>(1)?,
+                row.get::<usize, i64>(2)?,
+                row.get::<usize, i64>(3)?,
+            ))
+        })?;
+
+        // Spent and income per currency, both converted, and currencies which couldn't be.
+        type Sums = (BTreeMap<Currency, (i64, i64)>, i64, i64, BTreeSet<Currency>);
+        let mut months: HashMap<SpecificMonth, Sums> = HashMap::new();
+        for row in rows {
+            let (day, day_currency, spent, income) = row?;
+            let (per_currency, converted_spent, converted_income, unknown_rates) =
+                months.entry(SpecificMonth::from(day)).or_default();
+            let sums = per_currency.entry(day_currency).or_default();
+            sums.0 += spent;
+            sums.1 += income;
+            let convert = |amount| rates.convert(Money::new(amount, day_currency), currency, day);
+            match (convert(spent), convert(income)) {
+                (Some(spent), Some(income)) => {
+                    *converted_spent += spent.amount;
+                    *converted_income += income.amount;
+                }
+                _ => {
+                    unknown_rates.insert(day_currency);
+                }
+            }
         }
+
+        let totals = |spent, income, currency| Totals {
+            spent: Money::new(spent, currency),
+            income: Money::new(income, currency),
+            net: Money::new(income - spent, currency),
+        };
+        Ok(months
+            .into_iter()
+            .map(|(month, (per_currency, spent, income, unknown_rates))| {
+                let converted = (per_currency.len() > 1 || !per_currency.contains_key(&currency))
+                    .then(|| {
+                        let converted = totals(spent, income, currency);
+                        Totals {
+                            spent: converted.spent.rounded(),
+                            income: converted.income.rounded(),
+                            net: converted.net.rounded(),
+                        }
+                    });
+                let per_currency = per_currency
+                    .into_iter()
+                    .map(|(currency, (spent, income))| totals(spent, income, currency))
+                    .collect();
+                (
+                    month,
+                    MonthTotals {
+                        per_currency,
+                        converted,
+                        unknown_rates: unknown_rates.into_iter().collect(),
+                    },
+                )
+            })
+            .collect())
     }
 
-    let mut grouped_expenses = grouped_expenses.into_iter().collect::<Vec<_>>();
-    grouped_expenses.sort_by_key(|x| Reverse((x.0.year, x.0.month)));
+    fn get_budgets(&self) -> Result<Vec<Budget>> {
+        let mut statement = self
+            .connection
+            .prepare("select budgets.id, budgets.category_id, budgets.account_id, budgets.amount, budgets.currency from budgets left join categories on budgets.category_id = categories.id left join accounts on budgets.account_id = accounts.id order by categories.display_order, accounts.display_order")?;
+        let budgets = statement
+            .query_map([], |row| {
+                let category_id: Option<SqliteInteger> = row.get(1)?;
+                let account_id: Option<SqliteInteger> = row.get(2)?;
+                Ok(Budget {
+                    id: row.get(0)?,
+                    // The schema guarantees exactly one of them is set.
+                    target: match (category_id, account_id) {
+                        (Some(id), _) => BudgetTarget::Category(id),
+                        (None, id) => BudgetTarget::Account(id.unwrap()),
+                    },
+                    limit: Money::new(row.get(3)?, row.get(4)?),
+                })
+            })?
+            .collect();
+        budgets
+    }
 
-    grouped_expenses
-}
+    fn set_budget(&self, target: BudgetTarget, limit: Money) -> Result<()> {
+        let (category_id, account_id) = match target {
+            BudgetTarget::Category(id) => (Some(id), None),
+            BudgetTarget::Account(id) => (None, Some(id)),
+        };
+        // There's at most one budget per category or account, so replace the existing one.
+        let tx = self.connection.unchecked_transaction()?;
+        tx.execute(
+            "delete from budgets where category_id = ?1 or account_id = ?2",
+            (category_id, account_id),
+        )?;
+        tx.execute(
+            "insert into budgets (category_id, account_id, amount, currency) values (?1, ?2, ?3, ?4)",
+            (category_id, account_id, limit.amount, limit.currency),
+        )?;
+        tx.commit()
+    }
 
-async fn root() -> axum::response::Html<String> {
-    let repo = get_repo();
-    let grouped_expenses = get_grouped_expenses(&repo);
+    fn delete_budget(&self, id: SqliteInteger) -> Result<()> {
+        self.connection
+            .execute("delete from budgets where id = ?1", [id])?;
+        Ok(())
+    }
 
-    let r = render!(
-r#"{{ header }}
-    <form action="/add" method="post">
-        <input placeholder="Kremówki papieskie" autocomplete="off" name="name">
-        <input id="value" autocomplete="off" placeholder="21,37" inputmode="decimal" pattern="-?[0-9]+(,[0-9]{2})?" type="text" name="value">
-        <select name="account_id" id="account_id">
-            <option value="">-- Wybierz konto --</option>
-            {% for account in accounts %}
-              <option value="{{ account }}">[{{ accounts[account].currency }}] {{ accounts[account].name }}</option>
-            {% endfor %}
-        </select>
-        <input type="date" name="date" value="{{ today }}">
-        <button type="submit">Dodaj</button>
-    </form>
+    fn get_recurring(&self) -> Result<Vec<Recurring>> {
+        let mut statement = self
+            .connection
+            .prepare("select recurring.id, recurring.name, recurring.amount, accounts.currency, recurring.account_id, recurring.category_id, recurring.rule, recurring.day, recurring.start_date, recurring.end_date, recurring.last_date from recurring join accounts on recurring.account_id = accounts.id order by recurring.start_date, recurring.id")?;
+        let recurring = statement
+            .query_map([], |row| {
+                let kind: String = row.get(6)?;
+                let rule = Rule::parse(&kind, row.get(7)?).ok_or_else(|| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        6,
+                        rusqlite::types::Type::Text,
+                        format!("invalid rule {kind:?}").into(),
+                    )
+                })?;
+                Ok(Recurring {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    value: Money::new(row.get(2)?, row.get(3)?),
+                    account_id: row.get(4)?,
+                    category_id: row.get(5)?,
+                    rule,
+                    start_date: row.get(8)?,
+                    end_date: row.get(9)?,
+                    last_date: row.get(10)?,
+                })
+            })?
+            .collect();
+        recurring
+    }
 
-    {% for (month, expenses) in grouped_expenses %}
-        <details{% if loop.first %} open{% endif %}>
-        <summary>{{ month }}</summary>
-        {% for expense in expenses %}
-          <p>{{ expense | escape }}</p>
-        {% endfor %}
-        </details>
-    {% endfor %}
-    {{ footer }}
-"#,
-        header => HTML_HEADER,
-        footer => render_footer(),
-        accounts => repo.get_accounts(),
-        grouped_expenses => grouped_expenses,
-        today => chrono::offset::Utc::now().format("%Y-%m-%d").to_string(),
-    );
-    axum::response::Html(r)
-}
+    fn add_recurring(&self, recurring: &Recurring) -> Result<()> {
+        self.connection.execute(
+            "insert into recurring (name, amount, account_id, category_id, rule, day, start_date, end_date) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            (
+                &recurring.name,
+                recurring.value.amount,
+                recurring.account_id,
+                recurring.category_id,
+                recurring.rule.kind(),
+                recurring.rule.day(),
+                recurring.start_date.format("%Y-%m-%d").to_string(),
+                recurring.end_date.map(|d| d.format("%Y-%m-%d").to_string()),
+            ),
+        )?;
+        Ok(())
+    }
 
-async fn stats() -> axum::response::Html<String> {
-    let repo = get_repo();
-    let grouped_expenses = get_grouped_expenses(&repo);
+    fn delete_recurring(&self, id: SqliteInteger) -> Result<()> {
+        // Entries which have already been added stay.
+        self.connection
+            .execute("delete from recurring where id = ?1", [id])?;
+        Ok(())
+    }
 
-    let r = render!(
+    fn materialize_recurring(&self, today: NaiveDate) -> Result<usize> {
+        let mut added = 0;
+        for mut recurring in self.get_recurring()? {
+            // Adding the entries and remembering the last date is atomic, so each entry is
+            // added exactly once. The last date is read again under the write lock, as the
+            // scheduler and a newly added template may both be adding entries.
+            let tx = self.write_transaction()?;
+            let last_date = tx
+                .query_row(
+                    "select last_date from recurring where id = ?1",
+                    [recurring.id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            recurring.last_date = match last_date {
+                Some(last_date) => last_date,
+                // Deleted in the meantime.
+                None => continue,
+            };
+            let due = recurring.due(today);
+            let last = match due.last() {
+                Some(last) => *last,
+                None => continue,
+            };
+            for date in &due {
+                tx.execute(
+                    "insert into expenses (name, amount, date, account_id, category_id) values (?1, ?2, ?3, ?4, ?5)",
+                    (
+                        &recurring.name,
+                        recurring.value.amount,
+                        date.format("%Y-%m-%d").to_string(),
+                        recurring.account_id,
+                        recurring.category_id,
+                    ),
+                )?;
+            }
+            tx.execute(
+                "update recurring set last_date = ?1 where id = ?2",
+                (last.format("%Y-%m-%d").to_string(), recurring.id),
+            )?;
+            tx.commit()?;
+            added += due.len();
+        }
+        Ok(added)
+    }
+
+    fn get_import_mapping(&self, account_id: SqliteInteger) -> Result<import::Mapping> {
+        let mapping = self
+            .connection
+            .query_row(
+                "select delimiter, skip_rows, date_column, date_format, amount_column, description_column, negate from import_mappings where account_id = ?1",
+                [account_id],
+                |row| {
+                    let delimiter: String = row.get(0)?;
+                    Ok(import::Mapping {
+                        delimiter: delimiter.chars().next().unwrap_or(';'),
+                        skip_rows: row.get(1)?,
+                        date_column: row.get(2)?,
+                        date_format: row.get(3)?,
+                        amount_column: row.get(4)?,
+                        description_column: row.get(5)?,
+                        negate: row.get(6)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(mapping.unwrap_or_default())
+    }
+
+    fn set_import_mapping(
+        &self,
+        account_id: SqliteInteger,
+        mapping: &import::Mapping,
+    ) -> Result<()> {
+        self.connection.execute(
+            "insert or replace into import_mappings (account_id, delimiter, skip_rows, date_column, date_format, amount_column, description_column, negate) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            (
+                account_id,
+                mapping.delimiter.to_string(),
+                mapping.skip_rows,
+                mapping.date_column,
+                &mapping.date_format,
+                mapping.amount_column,
+                mapping.description_column,
+                mapping.negate,
+            ),
+        )?;
+        Ok(())
+    }
+
+    fn find_duplicate(
+        &self,
+        account_id: SqliteInteger,
+        date: NaiveDate,
+        amount: i64,
+        name: &str,
+    ) -> Result<Option<Expense>> {
+        let mut statement = self.connection.prepare(&format!(
+            "{EXPENSE_SELECT} where expenses.account_id = ?1 and date(expenses.date) = ?2 and expenses.amount = ?3"
+        ))?;
+        let candidates = statement
+            .query_map(
+                (account_id, date.format("%Y-%m-%d").to_string(), amount),
+                expense_from_row,
+            )?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(candidates
+            .into_iter()
+            .find(|expense| import::is_similar(&expense.name, name)))
+    }
+
+    fn get_balance_per_account(&self) -> Result<HashMap<SqliteInteger, Money>> {
+        let mut statement = self.connection.prepare("select accounts.id, accounts.opening_balance - coalesce(sum(expenses.amount), 0), accounts.currency from accounts left join expenses on expenses.account_id = accounts.id group by accounts.id")?;
+        let id2balance = statement
+            .query_map([], |row| {
+                Ok((row.get(0)?, Money::new(row.get(1)?, row.get(2)?)))
+            })?
+            .collect();
+        id2balance
+    }
+
+    fn get_balance_history(
+        &self,
+        oldest: SpecificMonth,
+        newest: SpecificMonth,
+    ) -> Result<Vec<(SpecificMonth, HashMap<SqliteInteger, Money>)>> {
+        let accounts = self.get_accounts()?;
+        let mut statement = self.connection.prepare("select account_id, date(date, 'start of month') as month, sum(amount) from expenses where date < ?1 group by account_id, month order by month")?;
+        let mut changes = statement
+            .query_map([newest.next().first_day()], |row| {
+                Ok((
+                    row.get::<usize, SqliteInteger>(0)?,
+                    SpecificMonth::from(row.get::<usize, NaiveDate>(1)?),
+                    row.get::<usize, i64>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .peekable();
+
+        let mut balances = accounts
+            .iter()
+            .map(|(id, account)| (*id, account.opening_balance))
+            .collect::<HashMap<_, _>>();
+        let mut history = Vec::new();
+        let mut month = oldest;
+        while month <= newest {
+            while let Some((id, _, sum)) = changes.next_if(|(_, changed, _)| *changed <= month) {
+                if let Some(balance) = balances.get_mut(&id) {
+                    balance.amount -= sum;
+                }
+            }
+            history.push((month, balances.clone()));
+            month = month.next();
+        }
+        Ok(history)
+    }
+
+    fn add_transfer(&self, transfer: &NewTransfer) -> Result<SqliteInteger> {
+        // `add` uses the same connection, so it's part of the transaction.
+        let tx = self.connection.unchecked_transaction()?;
+        let from_id = self.add(
+            transfer.name.clone(),
+            transfer.from,
+            transfer.date,
+            transfer.from_account_id.to_string(),
+            None,
+        )?;
+        let to_id = self.add(
+            transfer.name.clone(),
+            -transfer.to,
+            transfer.date,
+            transfer.to_account_id.to_string(),
+            None,
+        )?;
+        tx.execute(
+            "insert into transfers (from_expense_id, to_expense_id) values (?1, ?2)",
+            (from_id, to_id),
+        )?;
+        let id = tx.last_insert_rowid() as SqliteInteger;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    fn get_transfer(&self, id: SqliteInteger) -> Result<Option<Transfer>> {
+        let mut statement = self
+            .connection
+            .prepare(&format!("{EXPENSE_SELECT} where transfers.id = ?1"))?;
+        let mut sides = statement
+            .query_map([id], expense_from_row)?
+            .map(|expense| expense.map(|expense| (expense.id, expense)))
+            .collect::<Result<HashMap<_, _>>>()?;
+        let (from_id, to_id) = match self.get_transfer_sides(id).optional()? {
+            Some(sides) => sides,
+            None => return Ok(None),
+        };
+        Ok(sides
+            .remove(&from_id)
+            .zip(sides.remove(&to_id))
+            .map(|(from, to)| Transfer { id, from, to }))
+    }
+
+    fn get_transfers(
+        &self,
+        oldest: SpecificMonth,
+        newest: SpecificMonth,
+    ) -> Result<HashMap<SqliteInteger, Transfer>> {
+        const IN_RANGE: &str = "select transfers.id, transfers.from_expense_id, transfers.to_expense_id from transfers join expenses on transfers.from_expense_id = expenses.id where expenses.date >= ?1 and expenses.date < ?2";
+        let range = (oldest.first_day(), newest.next().first_day());
+        let mut statement = self.connection.prepare(&format!(
+            "{EXPENSE_SELECT} where transfers.id in (select id from ({IN_RANGE}))"
+        ))?;
+        let mut sides = statement
+            .query_map(range, expense_from_row)?
+            .map(|expense| expense.map(|expense| (expense.id, expense)))
+            .collect::<Result<HashMap<_, _>>>()?;
+        let mut statement = self.connection.prepare(IN_RANGE)?;
+        let ids = statement
+            .query_map(range, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<Vec<(SqliteInteger, SqliteInteger, SqliteInteger)>>>()?;
+        Ok(ids
+            .into_iter()
+            .filter_map(|(id, from_id, to_id)| {
+                let transfer = Transfer {
+                    id,
+                    from: sides.remove(&from_id)?,
+                    to: sides.remove(&to_id)?,
+                };
+                Some((id, transfer))
+            })
+            .collect())
+    }
+
+    fn update_transfer(&self, id: SqliteInteger, transfer: &NewTransfer) -> Result<()> {
+        let tx = self.write_transaction()?;
+        let (from_id, to_id) = self.get_transfer_sides(id)?;
+        self.update(
+            from_id,
+            transfer.name.clone(),
+            transfer.from,
+            transfer.date,
+            transfer.from_account_id.to_string(),
+            None,
+        )?;
+        self.update(
+            to_id,
+            transfer.name.clone(),
+            -transfer.to,
+            transfer.date,
+            transfer.to_account_id.to_string(),
+            None,
+        )?;
+        tx.commit()
+    }
+
+    fn delete_transfer(&self, id: SqliteInteger) -> Result<()> {
+        let tx = self.write_transaction()?;
+        let (from_id, to_id) = self.get_transfer_sides(id)?;
+        tx.execute("delete from transfers where id = ?1", [id])?;
+        tx.execute(
+            "delete from expenses where id in (?1, ?2)",
+            (from_id, to_id),
+        )?;
+        tx.commit()
+    }
+}
+
+impl SQLiteRepository {
+    /// The `where` clause selecting expenses which match `search`, for queries joining
+    /// `expenses` with `accounts`, and its parameters.
+    fn search_condition(&self, search: &Search) -> Result<(String, Vec<Box<dyn rusqlite::ToSql>>)> {
+        let mut conditions = Vec::new();
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+        if let Some(account_id) = search.filter.account_id {
+            conditions.push("expenses.account_id = ?".to_string());
+            params.push(Box::new(account_id));
+        }
+        if let Some(currency) = search.filter.currency {
+            conditions.push("accounts.currency = ?".to_string());
+            params.push(Box::new(currency));
+        }
+        if let Some(from) = search.filter.from {
+            conditions.push("expenses.date >= ?".to_string());
+            params.push(Box::new(from));
+        }
+        if let Some(to) = search.filter.to {
+            conditions.push("expenses.date < date(?, '+1 day')".to_string());
+            params.push(Box::new(to));
+        }
+        if let Some(name) = &search.name {
+            conditions.push("instr(unicode_lower(expenses.name), ?) > 0".to_string());
+            params.push(Box::new(name.clone()));
+        }
+        if let Some(query) = &search.fts {
+            conditions.push(
+                "expenses.id in (select rowid from expenses_fts where expenses_fts match ?)"
+                    .to_string(),
+            );
+            params.push(Box::new(query.clone()));
+        }
+        if let Some(incomes) = search.incomes {
+            conditions.push(
+                if incomes {
+                    "expenses.amount < 0"
+                } else {
+                    "expenses.amount >= 0"
+                }
+                .to_string(),
+            );
+        }
+        if search.min.is_some() || search.max.is_some() {
+            // The bounds are in thousandths, the amounts in minor units of their currency.
+            let scale = account_currencies(&self.get_accounts()?)
+                .iter()
+                .map(|currency| {
+                    format!(
+                        "when '{}' then {}",
+                        currency.code(),
+                        10_i64.pow(SEARCH_AMOUNT_DIGITS - currency.minor_digits())
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            let amount = format!("abs(expenses.amount) * case accounts.currency {scale} end");
+            if let Some(min) = search.min {
+                conditions.push(format!("{amount} >= ?"));
+                params.push(Box::new(min));
+            }
+            if let Some(max) = search.max {
+                conditions.push(format!("{amount} <= ?"));
+                params.push(Box::new(max));
+            }
+        }
+        let condition = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("where {}", conditions.join(" and "))
+        };
+        Ok((condition, params))
+    }
+
+    /// A transaction which takes the write lock right away. Transactions which read before
+    /// writing need it: upgrading a deferred one to a writer fails immediately if another
+    /// connection has written in the meantime, without waiting for the busy timeout.
+    fn write_transaction(&self) -> Result<rusqlite::Transaction<'_>> {
+        rusqlite::Transaction::new_unchecked(
+            &self.connection,
+            rusqlite::TransactionBehavior::Immediate,
+        )
+    }
+
+    /// IDs of the expense and the income making up a transfer.
+    fn get_transfer_sides(&self, id: SqliteInteger) -> Result<(SqliteInteger, SqliteInteger)> {
+        self.connection.query_row(
+            "select from_expense_id, to_expense_id from transfers where id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+    }
+}
+
+/// Groups expenses sorted newest first by month.
+fn group_by_month(expenses: Vec<Expense>) -> Vec<(SpecificMonth, Vec<Expense>)> {
+    let mut grouped_expenses: Vec<(SpecificMonth, Vec<Expense>)> = Vec::new();
+    for expense in expenses {
+        let month = SpecificMonth::from(expense.date);
+        match grouped_expenses.last_mut() {
+            Some((last, v)) if *last == month => v.push(expense),
+            _ => grouped_expenses.push((month, vec![expense])),
+        }
+    }
+    grouped_expenses
+}
+
+/// Everything on the accounts at the end of a month.
+#[derive(Serialize)]
+struct NetWorth {
+    month: SpecificMonth,
+    per_account: HashMap<SqliteInteger, Money>,
+    per_currency: BTreeMap<Currency, Money>,
+    /// All of `per_currency` at the rates valid on the last day of the month, rounded like other
+    /// converted totals. Currencies without any known rate are left out.
+    total: Money,
+}
+
+/// Net worth at the end of each month from `oldest` through `newest`, oldest first, with the
+/// total in `currency`. The current month uses today's balances and rates.
+fn get_net_worth_history(
+    repo: &SQLiteRepository,
+    oldest: SpecificMonth,
+    newest: SpecificMonth,
+    currency: Currency,
+) -> Result<Vec<NetWorth>> {
+    let rates = repo.get_exchange_rates()?;
+    let today = chrono::offset::Local::now().date_naive();
+    Ok(repo
+        .get_balance_history(oldest, newest)?
+        .into_iter()
+        .map(|(month, per_account)| {
+            let date = month.last_day().min(today);
+            let mut per_currency = BTreeMap::new();
+            let mut total = Money::new(0, currency);
+            for balance in per_account.values() {
+                per_currency
+                    .entry(balance.currency)
+                    .or_insert(Money::new(0, balance.currency))
+                    .amount += balance.amount;
+                if let Some(converted) = rates.convert(*balance, currency, date) {
+                    total.amount += converted.amount;
+                }
+            }
+            NetWorth {
+                month,
+                per_account,
+                per_currency,
+                total: total.rounded(),
+            }
+        })
+        .collect())
+}
+
+/// How much is left of each budget in the given month, and the currencies of expenses which
+/// couldn't be counted for lack of rates.
+fn get_budget_statuses(
+    repo: &SQLiteRepository,
+    month: SpecificMonth,
+) -> Result<(Vec<BudgetStatus>, Vec<Currency>)> {
+    let accounts = repo.get_accounts()?;
+    let categories = repo.get_categories()?;
+    let rates = repo.get_exchange_rates()?;
+    let expenses = repo.list_months(month, month)?;
+
+    let mut unknown_rates = Vec::new();
+    let statuses = repo
+        .get_budgets()?
+        .into_iter()
+        .map(|budget| {
+            let limit = budget.limit;
+            let mut spent = Money::new(0, limit.currency);
+            // Moving money between own accounts isn't spending, and incomes such as refunds
+            // don't make up for it either.
+            for expense in expenses.iter().filter(|e| {
+                e.transfer_id.is_none() && e.value.amount > 0 && budget.target.matches(e)
+            }) {
+                // Category budgets may cover expenses in other currencies.
+                match rates.convert(expense.value, limit.currency, expense.date) {
+                    Some(value) => spent.amount += value.amount,
+                    None => unknown_rates.push(expense.value.currency),
+                }
+            }
+            BudgetStatus {
+                name: budget.target.name(&accounts, &categories),
+                limit,
+                spent,
+                left: Money::new(limit.amount - spent.amount, limit.currency),
+                over: Money::new(spent.amount - limit.amount, limit.currency),
+                exceeded: spent.amount > limit.amount,
+            }
+        })
+        .collect();
+    unknown_rates.sort();
+    unknown_rates.dedup();
+    Ok((statuses, unknown_rates))
+}
+
+const BUDGET_STATUSES: &str = r#"
+    {% if budgets %}
+        <p>Budżety ({{ month }}):</p>
+        <ul>
+            {% for budget in budgets %}
+                {% if budget.exceeded %}
+                    <li><mark>⚠️ {{ budget.name | e }}: przekroczono o {{ budget.over }} (wydano {{ budget.spent }} z {{ budget.limit }})</mark></li>
+                {% else %}
+                    <li>{{ budget.name | e }}: zostało {{ budget.left }} z {{ budget.limit }}</li>
+                {% endif %}
+            {% endfor %}
+        </ul>
+        {% if unknown_rates %}
+            <p><mark>Brak kursów dla: {{ unknown_rates | join(", ") }}. Budżety nie uwzględniają wydatków w tych walutach, <a href="/import">zaimportuj kursy</a>.</mark></p>
+        {% endif %}
+    {% endif %}
+"#;
+
+fn render_budget_statuses(repo: &SQLiteRepository) -> Result<String> {
+    let month = SpecificMonth::from(chrono::offset::Local::now().date_naive());
+    let (budgets, unknown_rates) = get_budget_statuses(repo, month)?;
+    Ok(render!(
+        BUDGET_STATUSES,
+        month => month,
+        budgets => budgets,
+        unknown_rates => unknown_rates,
+    ))
+}
+
+/// Validation messages for the submitted form, keyed by field name.
+type FieldErrors = HashMap<&'static str, String>;
+
+fn today() -> String {
+    chrono::offset::Local::now().format("%Y-%m-%d").to_string()
+}
+
+/// How many months of expenses the main page shows at once.
+const MONTHS_PER_PAGE: usize = 3;
+
+#[derive(Debug, Deserialize)]
+struct MainPage {
+    /// Show the months before this one instead of the newest ones.
+    #[serde(default, deserialize_with = "empty_as_none")]
+    before: Option<SpecificMonth>,
+}
+
+async fn root(
+    State(state): State<AppState>,
+    Query(page): Query<MainPage>,
+) -> Result<axum::response::Html<String>, AppError> {
+    state
+        .run(move |repo| {
+            let form = NewExpense {
+                name: String::new(),
+                value: String::new(),
+                account_id: String::new(),
+                category_id: String::new(),
+                date: today(),
+            };
+            render_root(&repo, &form, &FieldErrors::new(), page.before)
+        })
+        .await
+}
+
+fn render_root(
+    repo: &SQLiteRepository,
+    form: &NewExpense,
+    errors: &FieldErrors,
+    before: Option<SpecificMonth>,
+) -> Result<axum::response::Html<String>, AppError> {
+    // One month more than shown tells whether there are older ones.
+    let mut shown_months = repo.get_months(before, MONTHS_PER_PAGE + 1)?;
+    let older = if shown_months.len() > MONTHS_PER_PAGE {
+        shown_months.truncate(MONTHS_PER_PAGE);
+        shown_months.last().map(|month| month.param())
+    } else {
+        None
+    };
+    let (grouped_expenses, mut totals, transfers) =
+        match (shown_months.last(), shown_months.first()) {
+            (Some(oldest), Some(newest)) => (
+                group_by_month(repo.list_months(*oldest, *newest)?),
+                repo.get_monthly_totals(*oldest, *newest, repo.get_reporting_currency()?)?,
+                repo.get_transfers(*oldest, *newest)?,
+            ),
+            _ => (Vec::new(), HashMap::new(), HashMap::new()),
+        };
+    // Each transfer is shown once, in place of its sending side.
+    let months = grouped_expenses
+        .iter()
+        .map(|(month, expenses)| {
+            let entries = expenses
+                .iter()
+                .filter_map(
+                    |expense| match expense.transfer_id.and_then(|id| transfers.get(&id)) {
+                        Some(transfer) if transfer.from.id != expense.id => None,
+                        Some(transfer) => Some(minijinja::context! {
+                            href => format!("/transfer/{}", transfer.id),
+                            text => transfer.to_string(),
+                        }),
+                        None => Some(minijinja::context! {
+                            href => format!("/expense/{}", expense.id),
+                            text => expense.to_string(),
+                        }),
+                    },
+                )
+                .collect::<Vec<_>>();
+            (*month, totals.remove(month), entries)
+        })
+        .collect::<Vec<_>>();
+    let accounts = repo.get_accounts()?;
+
+    let r = render!(
+r#"{{ header }}
+    <form action="/add" method="post">
+        <input placeholder="Kremówki papieskie" autocomplete="off" name="name" value="{{ form.name | e }}">
+        <input id="value" autocomplete="off" placeholder="21,37" inputmode="decimal" pattern="[+\-]?[0-9 ]+([,.][0-9]{1,3})?" type="text" name="value" value="{{ form.value | e }}">
+        {% if errors.value %}<mark>{{ errors.value }}</mark>{% endif %}
+        <select name="account_id" id="account_id">
+            <option value="">-- Wybierz konto --</option>
+            {% for account in account_order if not accounts[account].archived or account == form.account_id %}
+              <option value="{{ account }}"{% if account == form.account_id %} selected{% endif %}>[{{ accounts[account].currency }}] {{ accounts[account].name | e }}</option>
+            {% endfor %}
+        </select>
+        {% if errors.account_id %}<mark>{{ errors.account_id }}</mark>{% endif %}
+        <select name="category_id" id="category_id">
+            <option value="">-- Bez kategorii --</option>
+            {% for category in categories %}
+              <option value="{{ category }}"{% if category == form.category_id %} selected{% endif %}>{{ categories[category].name | e }}</option>
+            {% endfor %}
+        </select>
+        {% if errors.category_id %}<mark>{{ errors.category_id }}</mark>{% endif %}
+        <input type="date" name="date" value="{{ form.date | e }}">
+        {% if errors.date %}<mark>{{ errors.date }}</mark>{% endif %}
+        <button type="submit">Dodaj</button>
+    </form>
+
+    {{ budgets }}
+
+    {% for (month, totals, entries) in months %}
+        <details{% if loop.first %} open{% endif %}>
+        <summary>{{ month }}
+            {% if totals %}<small>
+                {% for t in totals.per_currency %}wydatki {{ t.spent }}, przychody {{ t.income }}, bilans {{ t.net }}{% if not loop.last %}; {% endif %}{% endfor %}
+                {% if totals.unknown_rates %}(bez sumy, brak kursów dla: {{ totals.unknown_rates | join(", ") }}){% elif totals.converted %}(razem: wydatki ~{{ totals.converted.spent }}, przychody ~{{ totals.converted.income }}, bilans ~{{ totals.converted.net }}){% endif %}
+            </small>{% endif %}
+        </summary>
+        {% for entry in entries %}
+          <p><a href="{{ entry.href }}">{{ entry.text | escape }}</a></p>
+        {% endfor %}
+        </details>
+    {% endfor %}
+    <p>
+        {% if older %}<a href="/?before={{ older }}">Starsze</a>{% endif %}
+        {% if newer %}<a href="/">Najnowsze</a>{% endif %}
+    </p>
+    {{ footer }}
+"#,
+        header => HTML_HEADER,
+        footer => render_footer(),
+        older => older,
+        newer => before.is_some(),
+        account_order => account_order(&accounts),
+        accounts => accounts,
+        categories => repo.get_categories()?,
+        budgets => render_budget_statuses(repo)?,
+        months => months,
+        form => form.context(),
+        errors => errors,
+    );
+    Ok(axum::response::Html(r))
+}
+
+/// The search form as submitted. Empty fields don't filter.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct SearchForm {
+    q: String,
+    /// Set when `q` is an FTS5 query rather than a part of the name.
+    fts: Option<String>,
+    account_id: String,
+    currency: String,
+    min: String,
+    max: String,
+    from: String,
+    to: String,
+    /// "expenses", "incomes" or empty for both.
+    kind: String,
+}
+
+/// Decimal places of the amount bounds of searches, enough for any currency.
+const SEARCH_AMOUNT_DIGITS: u32 = 3;
+
+/// How many of the newest results the search page shows.
+const SEARCH_RESULTS: usize = 200;
+
+/// A validated `SearchForm`, or only a filter from the API. Criteria which are `None` don't
+/// filter.
+#[derive(Default)]
+struct Search {
+    filter: ExpenseFilter,
+    /// Lowercase, to ignore the case of names.
+    name: Option<String>,
+    fts: Option<String>,
+    /// Bounds of the absolute amount, in thousandths of the major unit.
+    min: Option<i64>,
+    max: Option<i64>,
+    incomes: Option<bool>,
+}
+
+impl SearchForm {
+    fn is_empty(&self) -> bool {
+        [
+            &self.q,
+            &self.account_id,
+            &self.currency,
+            &self.min,
+            &self.max,
+            &self.from,
+            &self.to,
+            &self.kind,
+        ]
+        .iter()
+        .all(|field| field.trim().is_empty())
+    }
+
+    /// The form as submitted, with the account id as a number to match the keys of `accounts`.
+    fn context(&self) -> minijinja::value::Value {
+        minijinja::context! {
+            q => self.q,
+            fts => self.fts.is_some(),
+            account_id => self.account_id.parse::<SqliteInteger>().ok(),
+            currency => self.currency,
+            min => self.min,
+            max => self.max,
+            from => self.from,
+            to => self.to,
+            kind => self.kind,
+        }
+    }
+
+    fn validate(
+        &self,
+        accounts: &HashMap<SqliteInteger, Account>,
+    ) -> std::result::Result<Search, FieldErrors> {
+        let mut errors = FieldErrors::new();
+        let account_id = match self.account_id.as_str() {
+            "" => None,
+            id => match id.parse() {
+                Ok(id) if accounts.contains_key(&id) => Some(id),
+                _ => {
+                    errors.insert("account_id", "Nieznane konto.".to_string());
+                    None
+                }
+            },
+        };
+        let currency = match self.currency.as_str() {
+            "" => None,
+            currency => currency.parse().map(Some).unwrap_or_else(|_| {
+                errors.insert("currency", "Nieznana waluta.".to_string());
+                None
+            }),
+        };
+        let mut date = |field, value: &str| match value {
+            "" => None,
+            value => parse_date(value).or_else(|| {
+                errors.insert(field, INVALID_DATE.to_string());
+                None
+            }),
+        };
+        let from = date("from", &self.from);
+        let to = date("to", &self.to);
+        let mut amount = |field, value: &str| match value.trim() {
+            "" => None,
+            value => match money::parse_minor_units(value, SEARCH_AMOUNT_DIGITS) {
+                Ok(amount) => Some(amount),
+                Err(e) => {
+                    errors.insert(field, e.to_string());
+                    None
+                }
+            },
+        };
+        let min = amount("min", &self.min);
+        let max = amount("max", &self.max);
+        let incomes = match self.kind.as_str() {
+            "expenses" => Some(false),
+            "incomes" => Some(true),
+            _ => None,
+        };
+        let q = self.q.trim();
+        let search = Search {
+            filter: ExpenseFilter {
+                account_id,
+                currency,
+                from,
+                to,
+            },
+            name: (!q.is_empty() && self.fts.is_none()).then(|| q.to_lowercase()),
+            fts: (!q.is_empty() && self.fts.is_some()).then(|| q.to_string()),
+            min,
+            max,
+            incomes,
+        };
+        if errors.is_empty() {
+            Ok(search)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+async fn search(
+    State(state): State<AppState>,
+    Query(form): Query<SearchForm>,
+) -> Result<axum::response::Html<String>, AppError> {
+    state
+        .run(move |repo| {
+            let accounts = repo.get_accounts()?;
+            let currency = repo.get_reporting_currency()?;
+            let mut errors = FieldErrors::new();
+            let mut results = Vec::new();
+            let mut found = 0;
+            let mut totals: HashMap<Currency, i64> = HashMap::new();
+            let mut total = 0;
+            if !form.is_empty() {
+                match form.validate(&accounts) {
+                    Ok(search) => match repo.find(&search, Some(SEARCH_RESULTS), 0) {
+                        Ok(expenses) => {
+                            results = expenses;
+                            found = repo.count(&search)?;
+                            let rates = repo.get_exchange_rates()?;
+                            for (date, sum) in repo.sum_per_day(&search)? {
+                                *totals.entry(sum.currency).or_default() += sum.amount;
+                                if let Some(converted) = rates.convert(sum, currency, date) {
+                                    total += converted.amount;
+                                }
+                            }
+                        }
+                        // Syntax errors in the FTS5 query.
+                        Err(rusqlite::Error::SqliteFailure(e, message))
+                            if search.fts.is_some() && e.code == rusqlite::ErrorCode::Unknown =>
+                        {
+                            errors.insert(
+                                "q",
+                                format!(
+                                    "Niepoprawne zapytanie FTS5: {}.",
+                                    message.unwrap_or_default()
+                                ),
+                            );
+                        }
+                        Err(e) => return Err(e.into()),
+                    },
+                    Err(e) => errors = e,
+                }
+            }
+
+            let mut totals = totals
+                .into_iter()
+                .map(|(currency, amount)| Money::new(amount, currency))
+                .collect::<Vec<_>>();
+            totals.sort_by_key(|total| total.currency);
+            let rows = results
+                .iter()
+                .map(|expense| {
+                    minijinja::context! {
+                        href => match expense.transfer_id {
+                            Some(id) => format!("/transfer/{}", id),
+                            None => format!("/expense/{}", expense.id),
+                        },
+                        date => expense.date.format("%Y-%m-%d").to_string(),
+                        name => expense.name,
+                        account => accounts[&expense.account_id].name,
+                        value => if expense.value.amount < 0 { format!("+{}", -expense.value) } else { expense.value.to_string() },
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let r = render!(
+r#"{{ header }}
+<form action="/search" method="get">
+    <input placeholder="Kremówki" autocomplete="off" name="q" value="{{ form.q | e }}">
+    <label><input type="checkbox" name="fts"{% if form.fts %} checked{% endif %}> Zapytanie FTS5 (np. <code>mleko OR ser</code>, <code>kremów*</code>)</label>
+    {% if errors.q %}<mark>{{ errors.q }}</mark>{% endif %}
+    <select name="account_id">
+        <option value="">-- Wszystkie konta --</option>
+        {% for account in account_order %}
+          <option value="{{ account }}"{% if account == form.account_id %} selected{% endif %}>[{{ accounts[account].currency }}] {{ accounts[account].name | e }}</option>
+        {% endfor %}
+    </select>
+    {% if errors.account_id %}<mark>{{ errors.account_id }}</mark>{% endif %}
+    <select name="currency">
+        <option value="">-- Wszystkie waluty --</option>
+        {% for currency in currencies %}
+          <option value="{{ currency }}"{% if currency == form.currency %} selected{% endif %}>{{ currency }}</option>
+        {% endfor %}
+    </select>
+    {% if errors.currency %}<mark>{{ errors.currency }}</mark>{% endif %}
+    <select name="kind">
+        <option value="">-- Wydatki i przychody --</option>
+        <option value="expenses"{% if form.kind == "expenses" %} selected{% endif %}>Wydatki</option>
+        <option value="incomes"{% if form.kind == "incomes" %} selected{% endif %}>Przychody</option>
+    </select>
+    <label>Kwota od <input autocomplete="off" placeholder="0,00" inputmode="decimal" pattern="[0-9 ]+([,.][0-9]{1,3})?" type="text" name="min" value="{{ form.min | e }}"></label>
+    {% if errors.min %}<mark>{{ errors.min }}</mark>{% endif %}
+    <label>do <input autocomplete="off" placeholder="21,37" inputmode="decimal" pattern="[0-9 ]+([,.][0-9]{1,3})?" type="text" name="max" value="{{ form.max | e }}"></label>
+    {% if errors.max %}<mark>{{ errors.max }}</mark>{% endif %}
+    <label>Od <input type="date" name="from" value="{{ form.from | e }}"></label>
+    {% if errors.from %}<mark>{{ errors.from }}</mark>{% endif %}
+    <label>Do <input type="date" name="to" value="{{ form.to | e }}"></label>
+    {% if errors.to %}<mark>{{ errors.to }}</mark>{% endif %}
+    <button type="submit">Szukaj</button>
+</form>
+{% if rows %}
+    <p>Znalezione: {{ found }}{% if found > rows | length %} (pokazane najnowsze {{ rows | length }}){% endif %}. Suma (wydatki minus przychody): {{ totals | join(", ") }}{% if totals | length > 1 %} (~{{ total }}){% endif %}.</p>
+    <table>
+        <tr><th>Data</th><th>Opis</th><th>Konto</th><th>Kwota</th></tr>
+        {% for row in rows %}
+            <tr><td>{{ row.date }}</td><td><a href="{{ row.href }}">{{ row.name | e }}</a></td><td>{{ row.account | e }}</td><td>{{ row.value }}</td></tr>
+        {% endfor %}
+    </table>
+{% elif searched and not errors %}
+    <p>Nic nie znaleziono.</p>
+{% endif %}
+{{ footer }}"#,
+                header => HTML_HEADER,
+                footer => render_footer(),
+                searched => !form.is_empty(),
+                form => form.context(),
+                errors => errors,
+                rows => rows,
+                found => found,
+                totals => totals,
+                total => Money::new(total, currency).rounded(),
+                currencies => account_currencies(&accounts),
+                account_order => account_order(&accounts),
+                accounts => accounts,
+            );
+            Ok(axum::response::Html(r))
+        })
+        .await
+}
+
+/// How many months the charts on the stats page cover, including the current one.
+const CHART_MONTHS: usize = 12;
+
+async fn stats(State(state): State<AppState>) -> Result<axum::response::Html<String>, AppError> {
+    state
+        .run(move |repo| {
+            let rates = repo.get_exchange_rates()?;
+            let currency = repo.get_reporting_currency()?;
+            let today = chrono::offset::Local::now().date_naive();
+            // The same totals as in the month summaries on the main page. Expenses in
+            // currencies without any known rate are left out, see `unknown_rates`.
+            let mut monthly_totals = match (repo.get_first_date()?, repo.get_months(None, 1)?.first()) {
+                (Some(first), Some(newest)) => repo
+                    .get_monthly_totals(SpecificMonth::from(first), *newest, currency)?
+                    .into_iter()
+                    .map(|(month, totals)| (month, (-totals.in_reporting_currency().net).rounded()))
+                    .collect::<Vec<_>>(),
+                _ => Vec::new(),
+            };
+            monthly_totals.sort_by_key(|(month, _)| Reverse(*month));
+            let balance = repo
+                .balance()?
+                .into_values()
+                .map(|balance| Ok((balance, repo.convert(balance, currency, today)?)))
+                .collect::<Result<Vec<_>>>()?;
+            // Like the net worth, this includes opening balances.
+            let total = balance
+                .iter()
+                .filter_map(|(_, converted)| converted.map(|converted| converted.amount))
+                .sum();
+            let balance = balance
+                .into_iter()
+                .map(|(balance, converted)| (balance, converted.map(|converted| converted.rounded())))
+                .collect::<Vec<_>>();
+            let accounts = repo.get_accounts()?;
+            let unknown_rates = account_currencies(&accounts)
+                .into_iter()
+                .filter(|c| *c != currency && !(rates.knows(*c) && rates.knows(currency)))
+                .collect::<Vec<_>>();
+
+            let newest = SpecificMonth::from(today);
+            let oldest = (1..CHART_MONTHS).fold(newest, |month, _| month.previous());
+            let chart_months = std::iter::successors(Some(oldest), |month| Some(month.next()))
+                .take(CHART_MONTHS)
+                .collect::<Vec<_>>();
+            let label = |month: SpecificMonth| format!("{:02}.{}", month.month, month.year);
+            let totals = repo.get_monthly_totals(oldest, newest, currency)?;
+            let spending = chart_months
+                .iter()
+                .map(|month| {
+                    let spent = totals.get(month).map_or(Money::new(0, currency), |totals| {
+                        totals.in_reporting_currency().spent
+                    });
+                    (label(*month), spent.rounded())
+                })
+                .collect::<Vec<_>>();
+            let category_totals = repo.get_category_totals(currency)?;
+            let mut category_spending: Vec<(String, Money)> = Vec::new();
+            for category in category_totals
+                .iter()
+                .filter(|(month, _)| *month >= oldest)
+                .flat_map(|(_, categories)| categories)
+            {
+                match category_spending.iter_mut().find(|(name, _)| *name == category.name) {
+                    Some((_, total)) => total.amount += category.total.amount,
+                    None => category_spending.push((category.name.clone(), category.total)),
+                }
+            }
+            // Categories of incomes would have no bar.
+            category_spending.retain(|(_, total)| total.amount > 0);
+            category_spending.sort_by_key(|(_, total)| Reverse(total.amount));
+            let mut net_worth = get_net_worth_history(&repo, oldest, newest, currency)?;
+            let net_worth_points = net_worth
+                .iter()
+                .map(|net_worth| (label(net_worth.month), net_worth.total))
+                .collect::<Vec<_>>();
+            // The table is newest first, like the other lists.
+            net_worth.reverse();
+
+            let r = render!(
+r#"{{ header }}
+    <p><strong>tl;dr: ~{{ total }} łącznie.</strong></p>
+    {% if unknown_rates %}
+        <p><mark>Brak kursów dla: {{ unknown_rates | join(", ") }}. Sumy ich nie uwzględniają, <a href="/import">zaimportuj kursy</a>.</mark></p>
+    {% endif %}
+    <form action="/stats/currency" method="post">
+        <label>Waluta podsumowań
+            <select name="currency">
+                {% for c in currencies %}
+                  <option value="{{ c }}"{% if c == currency %} selected{% endif %}>{{ c }}</option>
+                {% endfor %}
+            </select>
+        </label>
+        <button type="submit">Zmień</button>
+    </form>
+    {{ budgets }}
+    <p>Wydatki w ostatnich {{ chart_months }} miesiącach:</p>
+    {{ spending_chart }}
+    <p>Majątek netto na koniec miesiąca:</p>
+    {{ net_worth_chart }}
+    <details>
+    <summary>Majątek netto per konto i per waluta</summary>
+    <table>
+        <tr>
+            <th>Miesiąc</th>
+            {% for acc in account_order %}<th>[{{ accounts[acc].currency }}] {{ accounts[acc].name | e }}</th>{% endfor %}
+            {% for c in balance_currencies %}<th>{{ c }}</th>{% endfor %}
+            <th>Razem</th>
+        </tr>
+        {% for row in net_worth %}
+            <tr>
+                <td>{{ row.month }}</td>
+                {% for acc in account_order %}<td>{{ row.per_account[acc] }}</td>{% endfor %}
+                {% for c in balance_currencies %}<td>{{ row.per_currency[c] }}</td>{% endfor %}
+                <td>~{{ row.total }}</td>
+            </tr>
+        {% endfor %}
+    </table>
+    <p><a href="/export">Eksport do CSV</a></p>
+    </details>
+    <p>Per miesiąc (wydatki minus przychody, po kursie z dnia wydatku):</p>
+    <ul>
+        {% for (month, total) in monthly_totals %}
+            <li>{{ month }}: ~{{ total }}</li>
+        {% endfor %}
+    </ul>
+    <p>Per waluta (po dzisiejszym kursie):</p>
+    <ul>
+        {% for (bal, converted) in balance %}
+            <li>{{ bal }}{% if converted and bal != converted %} (~{{ converted }}){% endif %}</li>
+        {% endfor %}
+    </ul>
+    <p>Per konto:</p>
+    <ul>
+        {% for acc in account_order %}
+            <li><a href="/accounts/{{ acc }}">[{{ accounts[acc].currency }}] {{ accounts[acc].name | e }}</a>: {{ acc_balance[acc] }}</li>
+        {% endfor %}
+    </ul>
+    <p>Per kategoria (wykres z ostatnich {{ chart_months }} miesięcy):</p>
+    {{ category_chart }}
+    {% for (month, categories) in category_totals %}
+        <details{% if loop.first %} open{% endif %}>
+        <summary>{{ month }}</summary>
+        <ul>
+            {% for category in categories %}
+                <li>{{ category.name | e }}: {{ category.totals | join(", ") }} (~{{ category.total }})</li>
+            {% endfor %}
+        </ul>
+        </details>
+    {% endfor %}
+    <p>{{ notepad }}</p>
+    {{ footer }}
+"#,
+                header => HTML_HEADER,
+                footer => render_footer(),
+                balance => balance,
+                notepad => repo.get_notepad()?,
+                budgets => render_budget_statuses(&repo)?,
+                total => Money::new(total, currency).rounded(),
+                currency => currency,
+                currencies => Currency::all().collect::<Vec<_>>(),
+                unknown_rates => unknown_rates,
+                account_order => account_order(&accounts),
+                accounts => accounts,
+                acc_balance => repo.get_balance_per_account()?,
+                category_totals => category_totals,
+                spending_chart => charts::bars("Wydatki per miesiąc", &spending),
+                net_worth_chart => charts::line("Majątek netto", &net_worth_points),
+                net_worth => net_worth,
+                balance_currencies => account_currencies(&accounts),
+                category_chart => charts::horizontal_bars("Wydatki per kategoria", &category_spending),
+                chart_months => CHART_MONTHS,
+                monthly_totals => monthly_totals,
+            );
+            Ok(axum::response::Html(r))
+        })
+        .await
+}
+
+#[derive(Debug, Deserialize)]
+struct ReportingCurrency {
+    currency: Currency,
+}
+
+async fn set_reporting_currency(
+    State(state): State<AppState>,
+    Form(form): Form<ReportingCurrency>,
+) -> Result<Redirect, AppError> {
+    state
+        .run(move |repo| {
+            repo.set_reporting_currency(form.currency)?;
+            Ok(Redirect::to("/stats"))
+        })
+        .await
+}
+
+async fn own_transfer(
+    State(state): State<AppState>,
+) -> Result<axum::response::Html<String>, AppError> {
+    state
+        .run(move |repo| {
+            let form = NewOwnTransfer {
+                account_id_from: String::new(),
+                value_from: String::new(),
+                account_id_to: String::new(),
+                value_to: String::new(),
+                date: today(),
+            };
+            render_own_transfer(&repo, None, &form, &FieldErrors::new())
+        })
+        .await
+}
+
+/// The form for a new transfer, or for editing the one with the given ID.
+fn render_own_transfer(
+    repo: &SQLiteRepository,
+    id: Option<SqliteInteger>,
+    form: &NewOwnTransfer,
+    errors: &FieldErrors,
+) -> Result<axum::response::Html<String>, AppError> {
+    let accounts = repo.get_accounts()?;
+    let r = render!(
+r#"{{ header }}
+<form action="{% if id %}/transfer/{{ id }}{% else %}/add_own{% endif %}" method="post">
+    <select name="account_id_from" id="account_id_from">
+        <option value="">-- Wybierz konto wysyłające --</option>
+        {% for account in account_order if not accounts[account].archived or account == form.account_id_from %}
+          <option value="{{ account }}"{% if account == form.account_id_from %} selected{% endif %}>[{{ accounts[account].currency }}] {{ accounts[account].name | e }}</option>
+        {% endfor %}
+    </select>
+    {% if errors.account_id_from %}<mark>{{ errors.account_id_from }}</mark>{% endif %}
+    <input id="value_from" autocomplete="off" placeholder="21,37" inputmode="decimal" pattern="[+\-]?[0-9 ]+([,.][0-9]{1,3})?" type="text" name="value_from" value="{{ form.value_from | e }}">
+    {% if errors.value_from %}<mark>{{ errors.value_from }}</mark>{% endif %}
+    <select name="account_id_to" id="account_id_to">
+        <option value="">-- Wybierz konto odbierające --</option>
+        {% for account in account_order if not accounts[account].archived or account == form.account_id_to %}
+          <option value="{{ account }}"{% if account == form.account_id_to %} selected{% endif %}>[{{ accounts[account].currency }}] {{ accounts[account].name | e }}</option>
+        {% endfor %}
+    </select>
+    {% if errors.account_id_to %}<mark>{{ errors.account_id_to }}</mark>{% endif %}
+    <input id="value_to" autocomplete="off" placeholder="21,37" inputmode="decimal" pattern="[+\-]?[0-9 ]+([,.][0-9]{1,3})?" type="text" name="value_to" value="{{ form.value_to | e }}">
+    {% if errors.value_to %}<mark>{{ errors.value_to }}</mark>{% endif %}
+    <input type="date" name="date" value="{{ form.date | e }}">
+    {% if errors.date %}<mark>{{ errors.date }}</mark>{% endif %}
+    <button type="submit">{% if id %}Zapisz{% else %}Dodaj{% endif %}</button>
+</form>
+{% if id %}
+<form action="/transfer/{{ id }}/delete" method="post">
+    <button type="submit">Usuń</button>
+</form>
+{% endif %}
+{{ footer }}"#,
+        header => HTML_HEADER,
+        footer => render_footer(),
+        account_order => account_order(&accounts),
+        accounts => accounts,
+        id => id,
+        form => form.context(),
+        errors => errors,
+    );
+
+    Ok(axum::response::Html(r))
+}
+
+const INVALID_DATE: &str = "Niepoprawna data.";
+const NO_ACCOUNT: &str = "Wybierz konto.";
+const SAME_ACCOUNT: &str = "Wybierz inne konto niż źródłowe.";
+const NOT_POSITIVE: &str = "Kwota przelewu musi być dodatnia.";
+const PART_OF_TRANSFER: &str = "To część przelewu własnego, zmień cały przelew.";
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+#[derive(Debug, Deserialize)]
+struct NewExpense {
+    name: String,
+    value: String,
+    account_id: String,
+    /// Missing when there are no categories to choose from.
+    #[serde(default)]
+    category_id: String,
+    date: String,
+}
+
+impl NewExpense {
+    /// The form as submitted, with ids as numbers to match the keys of `accounts` and
+    /// `categories`.
+    fn context(&self) -> minijinja::value::Value {
+        minijinja::context! {
+            name => self.name,
+            value => self.value,
+            account_id => self.account_id.parse::<SqliteInteger>().ok(),
+            category_id => self.category_id.parse::<SqliteInteger>().ok(),
+            date => self.date,
+        }
+    }
+
+    /// The value, date and category, or a message for each invalid field.
+    fn validate(
+        &self,
+        accounts: &HashMap<SqliteInteger, Account>,
+        categories: &HashMap<SqliteInteger, Category>,
+    ) -> std::result::Result<(Money, NaiveDate, Option<SqliteInteger>), FieldErrors> {
+        let mut errors = FieldErrors::new();
+        let account = self
+            .account_id
+            .parse()
+            .ok()
+            .and_then(|id| accounts.get(&id));
+        if account.is_none() {
+            errors.insert("account_id", NO_ACCOUNT.to_string());
+        }
+        let amount = match money::parse_amount(&self.value, minor_digits(account)) {
+            Ok(amount) => Some(amount),
+            Err(e) => {
+                errors.insert("value", e.to_string());
+                None
+            }
+        };
+        let date = parse_date(&self.date);
+        if date.is_none() {
+            errors.insert("date", INVALID_DATE.to_string());
+        }
+        // An empty value means "no category".
+        let category_id = match self.category_id.as_str() {
+            "" => None,
+            id => match id.parse() {
+                Ok(id) if categories.contains_key(&id) => Some(id),
+                _ => {
+                    errors.insert("category_id", "Nieznana kategoria.".to_string());
+                    None
+                }
+            },
+        };
+        match (account, amount, date) {
+            (Some(account), Some(amount), Some(date)) if errors.is_empty() => {
+                Ok((Money::new(amount, account.currency), date, category_id))
+            }
+            _ => Err(errors),
+        }
+    }
+}
+
+async fn add_expense(
+    State(state): State<AppState>,
+    Form(new_expense): Form<NewExpense>,
+) -> Result<Response, AppError> {
+    state
+        .run(move |repo| {
+            let (value, date, category_id) =
+                match new_expense.validate(&repo.get_accounts()?, &repo.get_categories()?) {
+                    Ok(parsed) => parsed,
+                    Err(errors) => {
+                        let page = render_root(&repo, &new_expense, &errors, None)?;
+                        return Ok((StatusCode::UNPROCESSABLE_ENTITY, page).into_response());
+                    }
+                };
+            repo.add(
+                new_expense.name,
+                value,
+                date,
+                new_expense.account_id,
+                category_id,
+            )?;
+            Ok(Redirect::to("/").into_response())
+        })
+        .await
+}
+
+async fn edit_expense(
+    State(state): State<AppState>,
+    Path(id): Path<SqliteInteger>,
+) -> Result<Response, AppError> {
+    state
+        .run(move |repo| {
+            let expense = repo.get(id)?.ok_or(AppError::NotFound)?;
+            if let Some(transfer_id) = expense.transfer_id {
+                return Ok(Redirect::to(&format!("/transfer/{transfer_id}")).into_response());
+            }
+            let form = NewExpense {
+                name: expense.name,
+                value: expense.value.input_value(),
+                account_id: expense.account_id.to_string(),
+                category_id: expense
+                    .category_id
+                    .map(|id| id.to_string())
+                    .unwrap_or_default(),
+                date: expense.date.format("%Y-%m-%d").to_string(),
+            };
+            Ok(render_edit_expense(&repo, id, &form, &FieldErrors::new())?.into_response())
+        })
+        .await
+}
+
+fn render_edit_expense(
+    repo: &SQLiteRepository,
+    id: SqliteInteger,
+    form: &NewExpense,
+    errors: &FieldErrors,
+) -> Result<axum::response::Html<String>, AppError> {
+    let accounts = repo.get_accounts()?;
+    let r = render!(
+r#"{{ header }}
+    <form action="/expense/{{ id }}" method="post">
+        <input placeholder="Kremówki papieskie" autocomplete="off" name="name" value="{{ form.name | escape }}">
+        <input id="value" autocomplete="off" placeholder="21,37" inputmode="decimal" pattern="[+\-]?[0-9 ]+([,.][0-9]{1,3})?" type="text" name="value" value="{{ form.value | e }}">
+        {% if errors.value %}<mark>{{ errors.value }}</mark>{% endif %}
+        <select name="account_id" id="account_id">
+            {% for account in account_order if not accounts[account].archived or account == form.account_id %}
+              <option value="{{ account }}"{% if account == form.account_id %} selected{% endif %}>[{{ accounts[account].currency }}] {{ accounts[account].name | e }}</option>
+            {% endfor %}
+        </select>
+        {% if errors.account_id %}<mark>{{ errors.account_id }}</mark>{% endif %}
+        <select name="category_id" id="category_id">
+            <option value="">-- Bez kategorii --</option>
+            {% for category in categories %}
+              <option value="{{ category }}"{% if category == form.category_id %} selected{% endif %}>{{ categories[category].name | e }}</option>
+            {% endfor %}
+        </select>
+        {% if errors.category_id %}<mark>{{ errors.category_id }}</mark>{% endif %}
+        <input type="date" name="date" value="{{ form.date | e }}">
+        {% if errors.date %}<mark>{{ errors.date }}</mark>{% endif %}
+        <button type="submit">Zapisz</button>
+    </form>
+    <form action="/expense/{{ id }}/delete" method="post">
+        <button type="submit">Usuń</button>
+    </form>
+    {{ footer }}
+"#,
+        header => HTML_HEADER,
+        footer => render_footer(),
+        account_order => account_order(&accounts),
+        accounts => accounts,
+        categories => repo.get_categories()?,
+        id => id,
+        form => form.context(),
+        errors => errors,
+    );
+    Ok(axum::response::Html(r))
+}
+
+async fn update_expense(
+    State(state): State<AppState>,
+    Path(id): Path<SqliteInteger>,
+    Form(expense): Form<NewExpense>,
+) -> Result<Response, AppError> {
+    state
+        .run(move |repo| {
+            let existing = repo.get(id)?.ok_or(AppError::NotFound)?;
+            if existing.transfer_id.is_some() {
+                return Err(AppError::BadRequest(PART_OF_TRANSFER.to_string()));
+            }
+            let (value, date, category_id) =
+                match expense.validate(&repo.get_accounts()?, &repo.get_categories()?) {
+                    Ok(parsed) => parsed,
+                    Err(errors) => {
+                        let page = render_edit_expense(&repo, id, &expense, &errors)?;
+                        return Ok((StatusCode::UNPROCESSABLE_ENTITY, page).into_response());
+                    }
+                };
+            repo.update(
+                id,
+                expense.name,
+                value,
+                date,
+                expense.account_id,
+                category_id,
+            )?;
+            Ok(Redirect::to("/").into_response())
+        })
+        .await
+}
+
+async fn delete_expense(
+    State(state): State<AppState>,
+    Path(id): Path<SqliteInteger>,
+) -> Result<Redirect, AppError> {
+    state
+        .run(move |repo| {
+            let expense = repo.get(id)?.ok_or(AppError::NotFound)?;
+            if expense.transfer_id.is_some() {
+                return Err(AppError::BadRequest(PART_OF_TRANSFER.to_string()));
+            }
+            repo.delete(id)?;
+            Ok(Redirect::to("/"))
+        })
+        .await
+}
+
+#[derive(Debug, Deserialize)]
+struct NewOwnTransfer {
+    account_id_from: String,
+    value_from: String,
+    account_id_to: String,
+    value_to: String,
+    date: String,
+}
+
+impl NewOwnTransfer {
+    /// The form as submitted, see `NewExpense::context`.
+    fn context(&self) -> minijinja::value::Value {
+        minijinja::context! {
+            account_id_from => self.account_id_from.parse::<SqliteInteger>().ok(),
+            value_from => self.value_from,
+            account_id_to => self.account_id_to.parse::<SqliteInteger>().ok(),
+            value_to => self.value_to,
+            date => self.date,
+        }
+    }
+
+    /// The transfer to store, or a message for each invalid field.
+    fn validate(
+        &self,
+        accounts: &HashMap<SqliteInteger, Account>,
+    ) -> std::result::Result<NewTransfer, FieldErrors> {
+        let mut errors = FieldErrors::new();
+        let date = parse_date(&self.date);
+        if date.is_none() {
+            errors.insert("date", INVALID_DATE.to_string());
+        }
+        let account_from = self
+            .account_id_from
+            .parse()
+            .ok()
+            .and_then(|id| Some((id, accounts.get(&id)?)));
+        if account_from.is_none() {
+            errors.insert("account_id_from", NO_ACCOUNT.to_string());
+        }
+        let account_to = self
+            .account_id_to
+            .parse()
+            .ok()
+            .and_then(|id| Some((id, accounts.get(&id)?)));
+        match (account_from, account_to) {
+            (_, None) => {
+                errors.insert("account_id_to", NO_ACCOUNT.to_string());
+            }
+            (Some((from, _)), Some((to, _))) if from == to => {
+                errors.insert("account_id_to", SAME_ACCOUNT.to_string());
+            }
+            _ => {}
+        }
+        let amount_from = match money::parse_amount(
+            &self.value_from,
+            minor_digits(account_from.map(|(_, account)| account)),
+        ) {
+            Ok(amount) if amount > 0 => Some(amount),
+            // Which way the money goes is given by the accounts, "+20" would turn it around.
+            Ok(_) => {
+                errors.insert("value_from", NOT_POSITIVE.to_string());
+                None
+            }
+            Err(e) => {
+                errors.insert("value_from", e.to_string());
+                None
+            }
+        };
+        let amount_to = match money::parse_amount(
+            &self.value_to,
+            minor_digits(account_to.map(|(_, account)| account)),
+        ) {
+            Ok(amount) if amount > 0 => Some(amount),
+            // Which way the money goes is given by the accounts, "+20" would turn it around.
+            Ok(_) => {
+                errors.insert("value_to", NOT_POSITIVE.to_string());
+                None
+            }
+            Err(e) => {
+                errors.insert("value_to", e.to_string());
+                None
+            }
+        };
+        let (
+            date,
+            (from_account_id, account_from),
+            (to_account_id, account_to),
+            amount_from,
+            amount_to,
+        ) = match (date, account_from, account_to, amount_from, amount_to) {
+            (Some(date), Some(from), Some(to), Some(amount_from), Some(amount_to))
+                if errors.is_empty() =>
+            {
+                (date, from, to, amount_from, amount_to)
+            }
+            _ => return Err(errors),
+        };
+
+        let name = format!(
+            r#"Przesłanie z "[{}] {}" na "[{}] {}""#,
+            account_from.currency, account_from.name, account_to.currency, account_to.name,
+        );
+        Ok(NewTransfer {
+            name,
+            date,
+            from_account_id,
+            from: Money::new(amount_from, account_from.currency),
+            to_account_id,
+            to: Money::new(amount_to, account_to.currency),
+        })
+    }
+}
+
+async fn add_own_transfer(
+    State(state): State<AppState>,
+    Form(transfer): Form<NewOwnTransfer>,
+) -> Result<Response, AppError> {
+    state
+        .run(move |repo| match transfer.validate(&repo.get_accounts()?) {
+            Ok(new_transfer) => {
+                repo.add_transfer(&new_transfer)?;
+                Ok(Redirect::to("/").into_response())
+            }
+            Err(errors) => {
+                let page = render_own_transfer(&repo, None, &transfer, &errors)?;
+                Ok((StatusCode::UNPROCESSABLE_ENTITY, page).into_response())
+            }
+        })
+        .await
+}
+
+async fn edit_transfer(
+    State(state): State<AppState>,
+    Path(id): Path<SqliteInteger>,
+) -> Result<axum::response::Html<String>, AppError> {
+    state
+        .run(move |repo| {
+            let transfer = repo.get_transfer(id)?.ok_or(AppError::NotFound)?;
+            let form = NewOwnTransfer {
+                account_id_from: transfer.from.account_id.to_string(),
+                value_from: transfer.from.value.input_value(),
+                account_id_to: transfer.to.account_id.to_string(),
+                value_to: (-transfer.to.value).input_value(),
+                date: transfer.from.date.format("%Y-%m-%d").to_string(),
+            };
+            render_own_transfer(&repo, Some(id), &form, &FieldErrors::new())
+        })
+        .await
+}
+
+async fn update_transfer(
+    State(state): State<AppState>,
+    Path(id): Path<SqliteInteger>,
+    Form(transfer): Form<NewOwnTransfer>,
+) -> Result<Response, AppError> {
+    state
+        .run(move |repo| {
+            repo.get_transfer(id)?.ok_or(AppError::NotFound)?;
+            match transfer.validate(&repo.get_accounts()?) {
+                Ok(new_transfer) => {
+                    repo.update_transfer(id, &new_transfer)?;
+                    Ok(Redirect::to("/").into_response())
+                }
+                Err(errors) => {
+                    let page = render_own_transfer(&repo, Some(id), &transfer, &errors)?;
+                    Ok((StatusCode::UNPROCESSABLE_ENTITY, page).into_response())
+                }
+            }
+        })
+        .await
+}
+
+async fn delete_transfer(
+    State(state): State<AppState>,
+    Path(id): Path<SqliteInteger>,
+) -> Result<Redirect, AppError> {
+    state
+        .run(move |repo| {
+            repo.get_transfer(id)?.ok_or(AppError::NotFound)?;
+            repo.delete_transfer(id)?;
+            Ok(Redirect::to("/"))
+        })
+        .await
+}
+
+async fn categories(
+    State(state): State<AppState>,
+) -> Result<axum::response::Html<String>, AppError> {
+    state
+        .run(move |repo| render_categories(&repo, "", &FieldErrors::new()))
+        .await
+}
+
+fn render_categories(
+    repo: &SQLiteRepository,
+    name: &str,
+    errors: &FieldErrors,
+) -> Result<axum::response::Html<String>, AppError> {
+    let r = render!(
+r#"{{ header }}
+<ul>
+    {% for category in categories %}
+        <li>{{ categories[category].name | e }}</li>
+    {% endfor %}
+</ul>
+<form action="/categories" method="post">
+    <input placeholder="Jedzenie" autocomplete="off" name="name" value="{{ name | e }}" required>
+    {% if errors.name %}<mark>{{ errors.name }}</mark>{% endif %}
+    <button type="submit">Dodaj</button>
+</form>
+{{ footer }}"#,
+        header => HTML_HEADER,
+        footer => render_footer(),
+        categories => repo.get_categories()?,
+        name => name,
+        errors => errors,
+    );
+
+    Ok(axum::response::Html(r))
+}
+
+#[derive(Debug, Deserialize)]
+struct NewCategory {
+    name: String,
+}
+
+async fn add_category(
+    State(state): State<AppState>,
+    Form(category): Form<NewCategory>,
+) -> Result<Response, AppError> {
+    state
+        .run(move |repo| {
+            let name = category.name.trim();
+            let mut errors = FieldErrors::new();
+            if name.is_empty() {
+                errors.insert("name", "Podaj nazwę.".to_string());
+            } else {
+                match repo.add_category(name.to_string()) {
+                    Ok(()) => return Ok(Redirect::to("/categories").into_response()),
+                    // The name is unique.
+                    Err(rusqlite::Error::SqliteFailure(e, _))
+                        if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+                    {
+                        errors.insert("name", "Taka kategoria już istnieje.".to_string());
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            let page = render_categories(&repo, &category.name, &errors)?;
+            Ok((StatusCode::UNPROCESSABLE_ENTITY, page).into_response())
+        })
+        .await
+}
+
+async fn accounts(State(state): State<AppState>) -> Result<axum::response::Html<String>, AppError> {
+    state
+        .run(move |repo| render_accounts(&repo, None, None, &FieldErrors::new()))
+        .await
+}
+
+/// The accounts page. After a failed submission, the form of the `edited` account or the `new`
+/// one shows what was typed into it with the `errors`.
+fn render_accounts(
+    repo: &SQLiteRepository,
+    edited: Option<(SqliteInteger, &AccountChanges)>,
+    new: Option<&NewAccount>,
+    errors: &FieldErrors,
+) -> Result<axum::response::Html<String>, AppError> {
+    let accounts = repo.get_accounts()?;
+    let rows = account_order(&accounts)
+        .into_iter()
+        .map(|id| {
+            let account = &accounts[&id];
+            match edited {
+                Some((edited_id, changes)) if edited_id == id => minijinja::context! {
+                    id => id,
+                    name => changes.name,
+                    currency => account.currency,
+                    opening_balance => changes.opening_balance,
+                    archived => changes.archived.is_some(),
+                    errors => errors,
+                },
+                _ => minijinja::context! {
+                    id => id,
+                    name => account.name,
+                    currency => account.currency,
+                    opening_balance => account.opening_balance.input_value(),
+                    archived => account.archived,
+                    errors => FieldErrors::new(),
+                },
+            }
+        })
+        .collect::<Vec<_>>();
+    let new = match new {
+        Some(account) => minijinja::context! {
+            name => account.name,
+            currency => account.currency,
+            opening_balance => account.opening_balance,
+            errors => errors,
+        },
+        None => minijinja::context! {
+            name => "",
+            currency => Currency::PLN,
+            opening_balance => "",
+            errors => FieldErrors::new(),
+        },
+    };
+
+    let r = render!(
+r#"{{ header }}
+<p>Konta archiwalne nie są proponowane przy dodawaniu wpisów, ale ich historia zostaje. Saldo początkowe to stan konta przed pierwszym wpisem.</p>
+{% for account in accounts %}
+    <form action="/accounts/{{ account.id }}" method="post">
+        <strong>[{{ account.currency }}]</strong>
+        <input autocomplete="off" name="name" value="{{ account.name | e }}" required>
+        {% if account.errors.name %}<mark>{{ account.errors.name }}</mark>{% endif %}
+        <label>Saldo początkowe <input autocomplete="off" placeholder="0,00" inputmode="decimal" pattern="-?[0-9 ]+([,.][0-9]{1,3})?" type="text" name="opening_balance" value="{{ account.opening_balance | e }}"></label>
+        {% if account.errors.opening_balance %}<mark>{{ account.errors.opening_balance }}</mark>{% endif %}
+        <label><input type="checkbox" name="archived"{% if account.archived %} checked{% endif %}> Archiwalne</label>
+        <button type="submit">Zapisz</button>
+        <a href="/accounts/{{ account.id }}">Historia</a>
+    </form>
+    <form action="/accounts/{{ account.id }}/up" method="post">
+        <button type="submit"{% if loop.first %} disabled{% endif %}>W górę</button>
+    </form>
+    <form action="/accounts/{{ account.id }}/down" method="post">
+        <button type="submit"{% if loop.last %} disabled{% endif %}>W dół</button>
+    </form>
+{% endfor %}
+<form action="/accounts" method="post">
+    <input placeholder="Konto osobiste" autocomplete="off" name="name" value="{{ new.name | e }}" required>
+    {% if new.errors.name %}<mark>{{ new.errors.name }}</mark>{% endif %}
+    <select name="currency" required>
+        {% for currency in currencies %}
+          <option value="{{ currency }}"{% if currency == new.currency %} selected{% endif %}>{{ currency }}</option>
+        {% endfor %}
+    </select>
+    <label>Saldo początkowe <input autocomplete="off" placeholder="0,00" inputmode="decimal" pattern="-?[0-9 ]+([,.][0-9]{1,3})?" type="text" name="opening_balance" value="{{ new.opening_balance | e }}"></label>
+    {% if new.errors.opening_balance %}<mark>{{ new.errors.opening_balance }}</mark>{% endif %}
+    <button type="submit">Dodaj</button>
+</form>
+{{ footer }}"#,
+        header => HTML_HEADER,
+        footer => render_footer(),
+        accounts => rows,
+        new => new,
+        currencies => Currency::all().collect::<Vec<_>>(),
+    );
+
+    Ok(axum::response::Html(r))
+}
+
+/// Every entry of the account with the balance right after it, to reconcile it with bank
+/// statements.
+async fn account(
+    State(state): State<AppState>,
+    Path(id): Path<SqliteInteger>,
+) -> Result<axum::response::Html<String>, AppError> {
+    state
+        .run(move |repo| {
+            let account = repo
+                .get_accounts()?
+                .remove(&id)
+                .ok_or(AppError::NotFound)?;
+            let mut balance = account.opening_balance;
+            let entries = repo
+                .get_account_expenses(id)?
+                .into_iter()
+                .map(|expense| {
+                    balance = Money::new(balance.amount - expense.value.amount, balance.currency);
+                    (expense, balance)
+                })
+                .collect::<Vec<_>>();
+            // From the first month with an entry up to the current one, or a later one with an
+            // entry dated in the future.
+            let month_ends = match (entries.first(), entries.last()) {
+                (Some((first, _)), Some((last, _))) => repo
+                    .get_balance_history(
+                        SpecificMonth::from(first.date),
+                        SpecificMonth::from(last.date.max(chrono::offset::Local::now().date_naive())),
+                    )?
+                    .into_iter()
+                    .map(|(month, balances)| (month, balances[&id]))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            // Newest first, like on the main page.
+            let rows = entries
+                .iter()
+                .rev()
+                .map(|(expense, balance)| {
+                    minijinja::context! {
+                        href => match expense.transfer_id {
+                            Some(id) => format!("/transfer/{}", id),
+                            None => format!("/expense/{}", expense.id),
+                        },
+                        date => expense.date.format("%Y-%m-%d").to_string(),
+                        name => expense.name,
+                        change => -expense.value,
+                        balance => balance,
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let r = render!(
+r#"{{ header }}
+<p><strong>[{{ account.currency }}] {{ account.name | e }}</strong>{% if account.archived %} (archiwalne){% endif %}: saldo {{ balance }}, saldo początkowe {{ account.opening_balance }}.</p>
+<details>
+<summary>Saldo na koniec miesiąca</summary>
+<table>
+    {% for (month, balance) in month_ends %}
+        <tr><td>{{ month }}</td><td>{{ balance }}</td></tr>
+    {% endfor %}
+</table>
+</details>
+<table>
+    <tr><th>Data</th><th>Opis</th><th>Kwota</th><th>Saldo</th></tr>
+    {% for row in rows %}
+        <tr><td>{{ row.date }}</td><td><a href="{{ row.href }}">{{ row.name | e }}</a></td><td>{{ row.change }}</td><td>{{ row.balance }}</td></tr>
+    {% endfor %}
+</table>
+{{ footer }}"#,
+                header => HTML_HEADER,
+                footer => render_footer(),
+                balance => balance,
+                month_ends => month_ends.into_iter().rev().collect::<Vec<_>>(),
+                rows => rows,
+                account => account,
+            );
+
+            Ok(axum::response::Html(r))
+        })
+        .await
+}
+
+/// An opening balance typed into a form, positive when there's money on the account. Empty
+/// means zero.
+fn parse_opening_balance(value: &str, currency: Currency) -> Result<Money, money::AmountError> {
+    let amount = match value.trim() {
+        "" => 0,
+        value => money::parse_minor_units(value, currency.minor_digits())?,
+    };
+    Ok(Money::new(amount, currency))
+}
+
+/// A trimmed, non-empty account name and a valid opening balance, or messages for the fields
+/// which aren't.
+fn validate_account(
+    name: &str,
+    opening_balance: &str,
+    currency: Currency,
+) -> std::result::Result<(String, Money), FieldErrors> {
+    let mut errors = FieldErrors::new();
+    let name = name.trim();
+    if name.is_empty() {
+        errors.insert("name", "Podaj nazwę.".to_string());
+    }
+    match parse_opening_balance(opening_balance, currency) {
+        Ok(opening_balance) if errors.is_empty() => Ok((name.to_string(), opening_balance)),
+        Ok(_) => Err(errors),
+        Err(e) => {
+            errors.insert("opening_balance", e.to_string());
+            Err(errors)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct NewAccount {
+    name: String,
+    currency: Currency,
+    opening_balance: String,
+}
+
+async fn add_account(
+    State(state): State<AppState>,
+    Form(account): Form<NewAccount>,
+) -> Result<Response, AppError> {
+    state
+        .run(move |repo| {
+            match validate_account(&account.name, &account.opening_balance, account.currency) {
+                Ok((name, opening_balance)) => {
+                    repo.add_account(name, opening_balance)?;
+                    Ok(Redirect::to("/accounts").into_response())
+                }
+                Err(errors) => {
+                    let page = render_accounts(&repo, None, Some(&account), &errors)?;
+                    Ok((StatusCode::UNPROCESSABLE_ENTITY, page).into_response())
+                }
+            }
+        })
+        .await
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountChanges {
+    name: String,
+    opening_balance: String,
+    archived: Option<String>,
+}
+
+async fn update_account(
+    State(state): State<AppState>,
+    Path(id): Path<SqliteInteger>,
+    Form(changes): Form<AccountChanges>,
+) -> Result<Response, AppError> {
+    state
+        .run(move |repo| {
+            let account = repo.get_accounts()?.remove(&id).ok_or(AppError::NotFound)?;
+            match validate_account(&changes.name, &changes.opening_balance, account.currency) {
+                Ok((name, opening_balance)) => {
+                    repo.update_account(id, name, opening_balance, changes.archived.is_some())?;
+                    Ok(Redirect::to("/accounts").into_response())
+                }
+                Err(errors) => {
+                    let page = render_accounts(&repo, Some((id, &changes)), None, &errors)?;
+                    Ok((StatusCode::UNPROCESSABLE_ENTITY, page).into_response())
+                }
+            }
+        })
+        .await
+}
+
+async fn move_account(
+    State(state): State<AppState>,
+    Path((id, direction)): Path<(SqliteInteger, String)>,
+) -> Result<Redirect, AppError> {
+    let up = match direction.as_str() {
+        "up" => true,
+        "down" => false,
+        _ => return Err(AppError::NotFound),
+    };
+    state
+        .run(move |repo| {
+            repo.move_account(id, up)?;
+            Ok(Redirect::to("/accounts"))
+        })
+        .await
+}
+
+async fn budgets(State(state): State<AppState>) -> Result<axum::response::Html<String>, AppError> {
+    state
+        .run(move |repo| {
+            let accounts = repo.get_accounts()?;
+            let categories = repo.get_categories()?;
+            let budgets = repo
+                .get_budgets()?
+                .into_iter()
+                .map(|budget| {
+                    minijinja::context! {
+                        id => budget.id,
+                        name => budget.target.name(&accounts, &categories),
+                        target => budget.target.to_string(),
+                        limit => budget.limit.input_value(),
+                        currency => budget.limit.currency,
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let r = render!(
 r#"{{ header }}
-    <p><strong>tl;dr: ~€{{ total_eur }} łącznie.</strong></p>
-    <p>Per waluta:</p>
-    <ul>
-        {% for (cur, bal) in balance %}
-            <li>{{ cur }}: {{ bal }}</li>
-        {% endfor %}
-    </ul>
-    <p>Per konto:</p>
-    <ul>
-        {% for acc in acc_balance %}
-            <li>{{ acc | e }}: {{ acc_balance[acc] }}</li>
+<p>Miesięczne limity wydatków. Budżet konta jest zawsze w walucie konta.</p>
+{% for budget in budgets %}
+    <form action="/budgets" method="post">
+        <strong>{{ budget.name | e }}</strong>
+        <input type="hidden" name="target" value="{{ budget.target }}">
+        <input autocomplete="off" placeholder="500,00" inputmode="decimal" pattern="[0-9 ]+([,.][0-9]{1,3})?" type="text" name="limit" value="{{ budget.limit }}">
+        <select name="currency">
+            {% for currency in currencies %}
+              <option value="{{ currency }}"{% if currency == budget.currency %} selected{% endif %}>{{ currency }}</option>
+            {% endfor %}
+        </select>
+        <button type="submit">Zapisz</button>
+    </form>
+    <form action="/budgets/{{ budget.id }}/delete" method="post">
+        <button type="submit">Usuń</button>
+    </form>
+{% endfor %}
+<form action="/budgets" method="post">
+    <select name="target" required>
+        <option value="">-- Wybierz kategorię lub konto --</option>
+        <optgroup label="Kategorie">
+            {% for category in categories %}
+              <option value="category-{{ category }}">{{ categories[category].name | e }}</option>
+            {% endfor %}
+        </optgroup>
+        <optgroup label="Konta">
+            {% for account in account_order if not accounts[account].archived %}
+              <option value="account-{{ account }}">[{{ accounts[account].currency }}] {{ accounts[account].name | e }}</option>
+            {% endfor %}
+        </optgroup>
+    </select>
+    <input autocomplete="off" placeholder="500,00" inputmode="decimal" pattern="[0-9 ]+([,.][0-9]{1,3})?" type="text" name="limit">
+    <select name="currency">
+        {% for currency in currencies %}
+          <option value="{{ currency }}">{{ currency }}</option>
         {% endfor %}
-    </ul>
-    <p>{{ notepad }}</p>
-    {{ footer }}
-"#,
-        header => HTML_HEADER,
-        footer => render_footer(),
-        balance => repo.balance().iter().collect::<Vec<_>>(),
-        notepad => repo.get_notepad(),
-        total_eur => {
-            let mut total: f64 = 0.0;
-            total += grouped_expenses
-                .iter()
-                .map(|pair| pair.1.iter().map(|e| e.value * repo.to_eur_approx(e.currency)).sum::<f64>())
-                .sum::<f64>();
-            -total.floor() as i64
-        },
-        acc_balance => repo.get_balance_per_account(),
-    );
-    axum::response::Html(r)
+    </select>
+    <button type="submit">Dodaj</button>
+</form>
+{{ footer }}"#,
+                header => HTML_HEADER,
+                footer => render_footer(),
+                budgets => budgets,
+                currencies => account_currencies(&accounts),
+                account_order => account_order(&accounts),
+                accounts => accounts,
+                categories => categories,
+            );
+
+            Ok(axum::response::Html(r))
+        })
+        .await
 }
 
-async fn own_transfer() -> axum::response::Html<String> {
-    let r = render!(
+#[derive(Debug, Deserialize)]
+struct NewBudget {
+    target: String,
+    limit: String,
+    currency: Currency,
+}
+
+async fn set_budget(
+    State(state): State<AppState>,
+    Form(budget): Form<NewBudget>,
+) -> Result<Redirect, AppError> {
+    state
+        .run(move |repo| {
+            let target: BudgetTarget = budget
+                .target
+                .try_into()
+                .map_err(|_| AppError::BadRequest("Wybierz kategorię lub konto.".to_string()))?;
+            let currency = match target {
+                BudgetTarget::Account(id) => {
+                    repo.get_accounts()?
+                        .get(&id)
+                        .ok_or(AppError::BadRequest(NO_ACCOUNT.to_string()))?
+                        .currency
+                }
+                BudgetTarget::Category(_) => budget.currency,
+            };
+            let amount = money::parse_amount(&budget.limit, currency.minor_digits())
+                .map_err(|e| AppError::BadRequest(e.to_string()))?;
+            repo.set_budget(target, Money::new(amount, currency))?;
+            Ok(Redirect::to("/budgets"))
+        })
+        .await
+}
+
+async fn delete_budget(
+    State(state): State<AppState>,
+    Path(id): Path<SqliteInteger>,
+) -> Result<Redirect, AppError> {
+    state
+        .run(move |repo| {
+            repo.delete_budget(id)?;
+            Ok(Redirect::to("/budgets"))
+        })
+        .await
+}
+
+async fn recurring(
+    State(state): State<AppState>,
+) -> Result<axum::response::Html<String>, AppError> {
+    state
+        .run(move |repo| {
+            let accounts = repo.get_accounts()?;
+            let categories = repo.get_categories()?;
+            let recurring = repo
+                .get_recurring()?
+                .into_iter()
+                .map(|recurring| {
+                    let income = recurring.value.amount < 0;
+                    minijinja::context! {
+                        id => recurring.id,
+                        name => recurring.name,
+                        value => if income { format!("+{}", -recurring.value) } else { recurring.value.to_string() },
+                        account => format!("[{}] {}", recurring.value.currency, accounts[&recurring.account_id].name),
+                        category => recurring.category_id.map(|id| categories[&id].name.clone()),
+                        rule => recurring.rule.to_string(),
+                        start_date => recurring.start_date.format("%Y-%m-%d").to_string(),
+                        end_date => recurring.end_date.map(|d| d.format("%Y-%m-%d").to_string()),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let r = render!(
 r#"{{ header }}
-<form action="/add_own" method="post">
-    <select name="account_id_from" id="account_id_from">
-        <option value="">-- Wybierz konto wysyłające --</option>
-        {% for account in accounts %}
-          <option value="{{ account }}">[{{ accounts[account].currency }}] {{ accounts[account].name }}</option>
+<ul>
+    {% for r in recurring %}
+        <li>
+            {{ r.name | e }} ({{ r.value }}), {{ r.account | e }}{% if r.category %}, {{ r.category | e }}{% endif %}: {{ r.rule }}, od {{ r.start_date }}{% if r.end_date %} do {{ r.end_date }}{% endif %}
+            <form action="/recurring/{{ r.id }}/delete" method="post">
+                <button type="submit">Usuń</button>
+            </form>
+        </li>
+    {% endfor %}
+</ul>
+<form action="/recurring" method="post">
+    <input placeholder="Czynsz" autocomplete="off" name="name" required>
+    <input autocomplete="off" placeholder="21,37" inputmode="decimal" pattern="[+\-]?[0-9 ]+([,.][0-9]{1,3})?" type="text" name="value" required>
+    <select name="account_id" required>
+        <option value="">-- Wybierz konto --</option>
+        {% for account in account_order if not accounts[account].archived %}
+          <option value="{{ account }}">[{{ accounts[account].currency }}] {{ accounts[account].name | e }}</option>
         {% endfor %}
     </select>
-    <input id="value_from" autocomplete="off" placeholder="21,37" inputmode="decimal" pattern="-?[0-9]+(,[0-9]{2})?" type="text" name="value_from">
-    <select name="account_id_to" id="account_id_to">
-        <option value="">-- Wybierz konto odbierające --</option>
-        {% for account in accounts %}
-          <option value="{{ account }}">[{{ accounts[account].currency }}] {{ accounts[account].name }}</option>
+    <select name="category_id">
+        <option value="">-- Bez kategorii --</option>
+        {% for category in categories %}
+          <option value="{{ category }}">{{ categories[category].name | e }}</option>
         {% endfor %}
     </select>
-    <input id="value_to" autocomplete="off" placeholder="21,37" inputmode="decimal" pattern="-?[0-9]+(,[0-9]{2})?" type="text" name="value_to">
-    <input type="date" name="date" value="{{ today }}">
+    <select name="rule">
+        <option value="monthly">Co miesiąc, dnia:</option>
+        <option value="weekly">Co tydzień, w dniu tygodnia (1 = poniedziałek):</option>
+    </select>
+    <input type="number" name="day" min="1" max="31" value="1" required>
+    <label>Od <input type="date" name="start_date" value="{{ today }}" required></label>
+    <label>Do (opcjonalnie) <input type="date" name="end_date"></label>
     <button type="submit">Dodaj</button>
 </form>
 {{ footer }}"#,
-        header => HTML_HEADER,
-        footer => render_footer(),
-        accounts => get_repo().get_accounts(),
-        today => chrono::offset::Utc::now().format("%Y-%m-%d").to_string(),
-    );
+                header => HTML_HEADER,
+                footer => render_footer(),
+                recurring => recurring,
+                account_order => account_order(&accounts),
+                accounts => accounts,
+                categories => categories,
+                today => today(),
+            );
 
-    axum::response::Html(r)
+            Ok(axum::response::Html(r))
+        })
+        .await
 }
 
 #[derive(Debug, Deserialize)]
-struct NewExpense {
+struct NewRecurring {
     name: String,
     value: String,
     account_id: String,
-    date: String,
+    category_id: String,
+    rule: String,
+    day: u32,
+    start_date: String,
+    end_date: String,
 }
 
-async fn add_expense(Form(new_expense): Form<NewExpense>) -> Redirect {
-    let repo = get_repo();
-    let date = NaiveDate::parse_from_str(new_expense.date.as_str(), "%Y-%m-%d").unwrap();
-    let value = new_expense.value.replace(',', ".").parse().unwrap();
-    repo.add(new_expense.name, value, date, new_expense.account_id);
-    Redirect::to("/")
+async fn add_recurring(
+    State(state): State<AppState>,
+    Form(recurring): Form<NewRecurring>,
+) -> Result<Redirect, AppError> {
+    state
+        .run(move |repo| {
+            let bad_request = |message: &str| AppError::BadRequest(message.to_string());
+            let account_id = recurring
+                .account_id
+                .parse()
+                .map_err(|_| bad_request(NO_ACCOUNT))?;
+            let account = repo
+                .get_accounts()?
+                .remove(&account_id)
+                .ok_or(bad_request(NO_ACCOUNT))?;
+            let categories = repo.get_categories()?;
+            let category_id = match recurring.category_id.as_str() {
+                "" => None,
+                id => Some(
+                    id.parse()
+                        .ok()
+                        .filter(|id| categories.contains_key(id))
+                        .ok_or(bad_request("Nieznana kategoria."))?,
+                ),
+            };
+            repo.add_recurring(&Recurring {
+                id: 0,
+                name: recurring.name,
+                value: Money::new(
+                    money::parse_amount(&recurring.value, account.currency.minor_digits())
+                        .map_err(|e| bad_request(&e.to_string()))?,
+                    account.currency,
+                ),
+                account_id,
+                category_id,
+                rule: Rule::parse(&recurring.rule, recurring.day)
+                    .ok_or(bad_request("Niepoprawny dzień powtarzania."))?,
+                start_date: parse_date(&recurring.start_date).ok_or(bad_request(INVALID_DATE))?,
+                end_date: match recurring.end_date.as_str() {
+                    "" => None,
+                    date => Some(parse_date(date).ok_or(bad_request(INVALID_DATE))?),
+                },
+                last_date: None,
+            })?;
+            // Don't wait for the scheduler if something is already due.
+            repo.materialize_recurring(chrono::offset::Local::now().date_naive())?;
+            Ok(Redirect::to("/recurring"))
+        })
+        .await
 }
 
-#[derive(Debug, Deserialize)]
-struct NewOwnTransfer {
-    account_id_from: String,
-    value_from: String,
-    account_id_to: String,
-    value_to: String,
-    date: String,
+async fn delete_recurring(
+    State(state): State<AppState>,
+    Path(id): Path<SqliteInteger>,
+) -> Result<Redirect, AppError> {
+    state
+        .run(move |repo| {
+            repo.delete_recurring(id)?;
+            Ok(Redirect::to("/recurring"))
+        })
+        .await
 }
 
-async fn add_own_transfer(Form(transfer): Form<NewOwnTransfer>) -> Redirect {
-    let repo = get_repo();
-    let id2account = repo.get_accounts();
-    let date = NaiveDate::parse_from_str(transfer.date.as_str(), "%Y-%m-%d").unwrap();
-    let value_from = transfer.value_from.replace(',', ".").parse().unwrap();
-    let value_to: f64 = transfer.value_to.replace(',', ".").parse().unwrap();
-    let account_from = id2account
-        .get(&transfer.account_id_from.parse().unwrap())
-        .unwrap();
-    let account_to = id2account
-        .get(&transfer.account_id_to.parse().unwrap())
-        .unwrap();
+async fn import_page(
+    State(state): State<AppState>,
+) -> Result<axum::response::Html<String>, AppError> {
+    state
+        .run(move |repo| render_import(&repo, None, &FieldErrors::new()))
+        .await
+}
 
-    // TODO: don't abuse `Debug`.
-    let description = format!(
-        r#"Przesłanie z "[{:?}] {}" na "[{:?}] {}" [{} → {}]"#,
-        account_from.currency,
-        account_from.name,
-        account_to.currency,
-        account_to.name,
-        transfer.account_id_from,
-        transfer.account_id_to
-    );
-    repo.add(
-        description.clone(),
-        value_from,
-        date,
-        transfer.account_id_from,
+/// The import page. After a failed submission, the mapping form of the `edited` account shows
+/// what was typed into it with the `errors`.
+fn render_import(
+    repo: &SQLiteRepository,
+    edited: Option<(SqliteInteger, &ImportMapping)>,
+    errors: &FieldErrors,
+) -> Result<axum::response::Html<String>, AppError> {
+    let accounts = repo.get_accounts()?;
+    let mappings = accounts
+        .keys()
+        .map(|id| {
+            let context = match edited {
+                Some((edited_id, mapping)) if edited_id == *id => minijinja::context! {
+                    delimiter => mapping.delimiter,
+                    skip_rows => mapping.skip_rows,
+                    date_column => mapping.date_column,
+                    date_format => mapping.date_format,
+                    amount_column => mapping.amount_column,
+                    description_column => mapping.description_column,
+                    negate => mapping.negate.is_some(),
+                    errors => errors,
+                },
+                _ => {
+                    let mapping = repo.get_import_mapping(*id)?;
+                    minijinja::context! {
+                        delimiter => match mapping.delimiter {
+                            '\t' => "tab".to_string(),
+                            c => c.to_string(),
+                        },
+                        skip_rows => mapping.skip_rows,
+                        date_column => mapping.date_column,
+                        date_format => mapping.date_format,
+                        amount_column => mapping.amount_column,
+                        description_column => mapping.description_column,
+                        negate => mapping.negate,
+                        errors => FieldErrors::new(),
+                    }
+                }
+            };
+            Ok((*id, context))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    let r = render!(
+r#"{{ header }}
+<p>Import wyciągu bankowego (CSV) na wybrane konto. Przed dodaniem zobaczysz podgląd.</p>
+<form action="/import" method="post" enctype="multipart/form-data">
+    <select name="account_id" required>
+        <option value="">-- Wybierz konto --</option>
+        {% for account in account_order if not accounts[account].archived %}
+          <option value="{{ account }}">[{{ accounts[account].currency }}] {{ accounts[account].name | e }}</option>
+        {% endfor %}
+    </select>
+    <input type="file" name="file" accept=".csv,text/csv" required>
+    <button type="submit">Podgląd</button>
+</form>
+<p>Mapowanie kolumn (numerowane od 1) per konto:</p>
+{% for account in account_order if not accounts[account].archived %}
+    <details{% if mappings[account].errors %} open{% endif %}>
+    <summary>[{{ accounts[account].currency }}] {{ accounts[account].name | e }}</summary>
+    <form action="/import/mapping/{{ account }}" method="post">
+        <label>Separator
+            <select name="delimiter">
+                {% for d in [";", ",", "tab"] %}
+                  <option value="{{ d }}"{% if d == mappings[account].delimiter %} selected{% endif %}>{{ d }}</option>
+                {% endfor %}
+            </select>
+        </label>
+        {% if mappings[account].errors.delimiter %}<mark>{{ mappings[account].errors.delimiter }}</mark>{% endif %}
+        <label>Pominięte wiersze nagłówka <input type="number" name="skip_rows" min="0" value="{{ mappings[account].skip_rows }}" required></label>
+        <label>Kolumna z datą <input type="number" name="date_column" min="1" value="{{ mappings[account].date_column }}" required></label>
+        <label>Format daty <input name="date_format" value="{{ mappings[account].date_format | e }}" required></label>
+        <label>Kolumna z kwotą <input type="number" name="amount_column" min="1" value="{{ mappings[account].amount_column }}" required></label>
+        <label>Kolumna z opisem <input type="number" name="description_column" min="1" value="{{ mappings[account].description_column }}" required></label>
+        <label><input type="checkbox" name="negate"{% if mappings[account].negate %} checked{% endif %}> Wydatki jako kwoty ujemne</label>
+        <button type="submit">Zapisz</button>
+    </form>
+    </details>
+{% endfor %}
+<p>Import historii kursów walut: plik eurofxref-hist (CSV lub XML) z EBC albo tabela A kursów NBP (JSON lub XML). Kursy z tego samego dnia zostaną zastąpione.</p>
+<form action="/import/rates" method="post" enctype="multipart/form-data">
+    <input type="file" name="file" accept=".csv,.xml,.json" multiple required>
+    <button type="submit">Importuj kursy</button>
+</form>
+{{ footer }}"#,
+        header => HTML_HEADER,
+        footer => render_footer(),
+        account_order => account_order(&accounts),
+        accounts => accounts,
+        mappings => mappings,
     );
-    repo.add(description, -value_to, date, transfer.account_id_to);
-    Redirect::to("/")
+
+    Ok(axum::response::Html(r))
+}
+
+#[derive(Debug, Deserialize)]
+struct ImportMapping {
+    delimiter: String,
+    skip_rows: usize,
+    date_column: usize,
+    date_format: String,
+    amount_column: usize,
+    description_column: usize,
+    negate: Option<String>,
+}
+
+async fn set_import_mapping(
+    State(state): State<AppState>,
+    Path(account_id): Path<SqliteInteger>,
+    Form(mapping): Form<ImportMapping>,
+) -> Result<Response, AppError> {
+    state
+        .run(move |repo| {
+            let mut chars = mapping.delimiter.chars();
+            let delimiter = match (mapping.delimiter.as_str(), chars.next(), chars.next()) {
+                ("tab", _, _) => '\t',
+                // The CSV reader splits bytes, so the separator must fit in one.
+                (_, Some(c), None) if c.is_ascii() => c,
+                _ => {
+                    let mut errors = FieldErrors::new();
+                    errors.insert("delimiter", "Wybierz separator ASCII.".to_string());
+                    let page = render_import(&repo, Some((account_id, &mapping)), &errors)?;
+                    return Ok((StatusCode::UNPROCESSABLE_ENTITY, page).into_response());
+                }
+            };
+            repo.set_import_mapping(
+                account_id,
+                &import::Mapping {
+                    delimiter,
+                    skip_rows: mapping.skip_rows,
+                    date_column: mapping.date_column,
+                    date_format: mapping.date_format,
+                    amount_column: mapping.amount_column,
+                    description_column: mapping.description_column,
+                    negate: mapping.negate.is_some(),
+                },
+            )?;
+            Ok(Redirect::to("/import").into_response())
+        })
+        .await
+}
+
+async fn import_rates(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<axum::response::Html<String>, AppError> {
+    let upload_failed = |e: axum::extract::multipart::MultipartError| {
+        AppError::BadRequest(format!("Nie udało się wysłać pliku: {e}"))
+    };
+    let mut rates = Vec::new();
+    while let Some(field) = multipart.next_field().await.map_err(upload_failed)? {
+        if field.name() == Some("file") {
+            let name = field.file_name().unwrap_or_default().to_string();
+            let data = field.bytes().await.map_err(upload_failed)?;
+            rates.extend(rates::parse_file(&data).map_err(|e| {
+                AppError::BadRequest(format!("Nie udało się wczytać pliku {name}: {e}"))
+            })?);
+        }
+    }
+
+    state
+        .run(move |repo| {
+            repo.add_exchange_rates(&rates)?;
+            let r = render!(
+r#"{{ header }}
+<p>Zaimportowano kursów: {{ count }}.</p>
+<p><a href="/import">Wróć</a></p>
+{{ footer }}"#,
+                header => HTML_HEADER,
+                footer => render_footer(),
+                count => rates.len(),
+            );
+            Ok(axum::response::Html(r))
+        })
+        .await
+}
+
+async fn import_preview(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<axum::response::Html<String>, AppError> {
+    let upload_failed = |e: axum::extract::multipart::MultipartError| {
+        AppError::BadRequest(format!("Nie udało się wysłać pliku: {e}"))
+    };
+    let mut account_id: Option<SqliteInteger> = None;
+    let mut data = Vec::new();
+    while let Some(field) = multipart.next_field().await.map_err(upload_failed)? {
+        match field.name() {
+            Some("account_id") => {
+                account_id = field.text().await.map_err(upload_failed)?.parse().ok()
+            }
+            Some("file") => data = field.bytes().await.map_err(upload_failed)?.to_vec(),
+            _ => {}
+        }
+    }
+
+    state
+        .run(move |repo| {
+            let no_account = || AppError::BadRequest(NO_ACCOUNT.to_string());
+            let account_id = account_id.ok_or_else(no_account)?;
+            let account = repo
+                .get_accounts()?
+                .remove(&account_id)
+                .ok_or_else(no_account)?;
+            let (rows, error) = match import::parse_statement(&repo.get_import_mapping(account_id)?, account.currency, &data)
+            {
+                Ok(rows) => (rows, None),
+                Err(e) => (Vec::new(), Some(e)),
+            };
+            let rows = rows
+                .into_iter()
+                .map(|row| {
+                    let duplicate =
+                        repo.find_duplicate(account_id, row.date, row.amount, &row.description)?;
+                    Ok(minijinja::context! {
+                        value => format!("{};{};{}", row.date.format("%Y-%m-%d"), row.amount, row.description),
+                        date => row.date.format("%Y-%m-%d").to_string(),
+                        // Like `Expense`, show incomes with a plus.
+                        amount => match Money::new(row.amount, account.currency) {
+                            income if income.amount < 0 => format!("+{}", -income),
+                            expense => expense.to_string(),
+                        },
+                        description => row.description,
+                        duplicate => duplicate.map(|e| e.to_string()),
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            let r = render!(
+r#"{{ header }}
+{% if error %}
+    <p><mark>Nie udało się wczytać pliku: {{ error | e }}</mark></p>
+    <p><a href="/import">Wróć</a></p>
+{% else %}
+    <p>Podgląd importu na konto [{{ account.currency }}] {{ account.name | e }}. Możliwe duplikaty nie są domyślnie zaznaczone.</p>
+    <form action="/import/confirm" method="post">
+        <input type="hidden" name="account_id" value="{{ account_id }}">
+        <table>
+            <tr><th></th><th>Data</th><th>Kwota</th><th>Opis</th><th>Możliwy duplikat</th></tr>
+            {% for row in rows %}
+                <tr>
+                    <td><input type="checkbox" name="row-{{ loop.index }}" value="{{ row.value | e }}"{% if not row.duplicate %} checked{% endif %}></td>
+                    <td>{{ row.date }}</td>
+                    <td>{{ row.amount }}</td>
+                    <td>{{ row.description | e }}</td>
+                    <td>{% if row.duplicate %}{{ row.duplicate | e }}{% endif %}</td>
+                </tr>
+            {% endfor %}
+        </table>
+        <button type="submit">Importuj zaznaczone</button>
+    </form>
+{% endif %}
+{{ footer }}"#,
+                header => HTML_HEADER,
+                footer => render_footer(),
+                error => error,
+                account => account,
+                account_id => account_id,
+                rows => rows,
+            );
+
+            Ok(axum::response::Html(r))
+        })
+        .await
+}
+
+async fn import_confirm(
+    State(state): State<AppState>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Result<Redirect, AppError> {
+    state
+        .run(move |repo| {
+            let no_account = || AppError::BadRequest(NO_ACCOUNT.to_string());
+            let account_id: SqliteInteger = form
+                .get("account_id")
+                .and_then(|id| id.parse().ok())
+                .ok_or_else(no_account)?;
+            let currency = repo
+                .get_accounts()?
+                .remove(&account_id)
+                .ok_or_else(no_account)?
+                .currency;
+
+            let mut rows = form
+                .iter()
+                .filter_map(|(key, value)| {
+                    let index: usize = key.strip_prefix("row-")?.parse().ok()?;
+                    Some((index, value))
+                })
+                .collect::<Vec<_>>();
+            rows.sort_by_key(|(index, _)| *index);
+
+            // Expected format: "2022-12-31;2137;description", see `import_preview`.
+            let parse_row = |row: &str| {
+                let mut parts = row.splitn(3, ';');
+                let date = parse_date(parts.next()?)?;
+                let amount = parts.next()?.parse().ok()?;
+                let description = parts.next()?.to_string();
+                Some((description, Money::new(amount, currency), date))
+            };
+            let rows = rows
+                .into_iter()
+                .map(|(_, row)| {
+                    parse_row(row).ok_or_else(|| {
+                        AppError::BadRequest(format!("Niepoprawny wiersz importu: {row}"))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            repo.add_imported(account_id, rows)?;
+            Ok(Redirect::to("/"))
+        })
+        .await
+}
+
+async fn export_page(
+    State(state): State<AppState>,
+) -> Result<axum::response::Html<String>, AppError> {
+    state
+        .run(move |repo| {
+            let accounts = repo.get_accounts()?;
+            let r = render!(
+r#"{{ header }}
+<p>Eksport wszystkich wydatków i przychodów, opcjonalnie przefiltrowanych. Wydatki mają kwoty dodatnie, przychody ujemne (poza OFX).</p>
+<form action="/export/csv" method="get">
+    <select name="account_id">
+        <option value="">-- Wszystkie konta --</option>
+        {% for account in account_order %}
+          <option value="{{ account }}">[{{ accounts[account].currency }}] {{ accounts[account].name | e }}</option>
+        {% endfor %}
+    </select>
+    <select name="currency">
+        <option value="">-- Wszystkie waluty --</option>
+        {% for currency in currencies %}
+          <option value="{{ currency }}">{{ currency }}</option>
+        {% endfor %}
+    </select>
+    <label>Od <input type="date" name="from"></label>
+    <label>Do <input type="date" name="to"></label>
+    <button type="submit" formaction="/export/csv">CSV</button>
+    <button type="submit" formaction="/export/json">JSON</button>
+    <button type="submit" formaction="/export/ofx">OFX</button>
+</form>
+<p>Majątek netto na koniec każdego miesiąca: per konto, per waluta i razem w walucie podsumowań (po kursie z ostatniego dnia miesiąca). Domyślnie od pierwszego wpisu do dziś.</p>
+<form action="/export/net_worth" method="get">
+    <label>Od <input type="month" name="from"></label>
+    <label>Do <input type="month" name="to"></label>
+    <button type="submit">CSV</button>
+</form>
+{{ footer }}"#,
+                header => HTML_HEADER,
+                footer => render_footer(),
+                currencies => account_currencies(&accounts),
+                account_order => account_order(&accounts),
+                accounts => accounts,
+            );
+
+            Ok(axum::response::Html(r))
+        })
+        .await
+}
+
+#[derive(Debug, Deserialize)]
+struct MonthRange {
+    #[serde(default, deserialize_with = "empty_as_none")]
+    from: Option<SpecificMonth>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    to: Option<SpecificMonth>,
+}
+
+/// Net worth at the end of each month, from the month of the oldest expense through the
+/// current one unless chosen otherwise.
+async fn export_net_worth(
+    State(state): State<AppState>,
+    Query(range): Query<MonthRange>,
+) -> Result<impl axum::response::IntoResponse, AppError> {
+    state
+        .run(move |repo| {
+            let accounts = repo.get_accounts()?;
+            let currency = repo.get_reporting_currency()?;
+            let newest = range
+                .to
+                .unwrap_or_else(|| SpecificMonth::from(chrono::offset::Local::now().date_naive()));
+            let oldest = match range.from {
+                Some(from) => from,
+                None => repo.get_first_date()?.map_or(newest, SpecificMonth::from),
+            };
+            let history = get_net_worth_history(&repo, oldest, newest, currency)?;
+
+            Ok((
+                [
+                    (
+                        axum::http::header::CONTENT_TYPE,
+                        "text/csv; charset=utf-8".to_string(),
+                    ),
+                    (
+                        axum::http::header::CONTENT_DISPOSITION,
+                        "attachment; filename=\"casa-net-worth.csv\"".to_string(),
+                    ),
+                ],
+                export::net_worth_to_csv(&history, &accounts, currency),
+            ))
+        })
+        .await
+}
+
+/// Empty values (as sent by our forms) mean "no filter".
+fn empty_as_none<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: std::str::FromStr,
+    T::Err: Display,
+{
+    match Option::<String>::deserialize(deserializer)?.as_deref() {
+        None | Some("") => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Used by exports and the API.
+#[derive(Debug, Default, Deserialize)]
+struct ExpenseFilter {
+    #[serde(default, deserialize_with = "empty_as_none")]
+    account_id: Option<SqliteInteger>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    currency: Option<Currency>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    from: Option<NaiveDate>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    to: Option<NaiveDate>,
+}
+
+async fn export(
+    State(state): State<AppState>,
+    Path(format): Path<String>,
+    Query(filter): Query<ExpenseFilter>,
+) -> Result<impl axum::response::IntoResponse, AppError> {
+    state
+        .run(move |repo| {
+            let accounts = repo.get_accounts()?;
+            let categories = repo.get_categories()?;
+            let search = Search {
+                filter,
+                ..Search::default()
+            };
+            let mut found = repo.find(&search, None, 0)?;
+            // Oldest first, like in bank statements.
+            found.reverse();
+            let expenses = found.iter().collect::<Vec<_>>();
+
+            let (content_type, body) = match format.as_str() {
+                "csv" => (
+                    "text/csv; charset=utf-8",
+                    export::to_csv(&expenses, &accounts, &categories),
+                ),
+                "json" => (
+                    "application/json",
+                    export::to_json(&expenses, &accounts, &categories),
+                ),
+                "ofx" => {
+                    let balances = repo.get_balance_per_account()?;
+                    (
+                        "application/x-ofx",
+                        export::to_ofx(&expenses, &accounts, &balances),
+                    )
+                }
+                _ => return Err(AppError::NotFound),
+            };
+
+            Ok((
+                [
+                    (axum::http::header::CONTENT_TYPE, content_type.to_string()),
+                    (
+                        axum::http::header::CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"casa.{format}\""),
+                    ),
+                ],
+                body,
+            ))
+        })
+        .await
 }
 
 async fn manifest() -> impl axum::response::IntoResponse {
@@ -563,26 +3865,343 @@ async fn icon() -> impl axum::response::IntoResponse {
     )
 }
 
-fn get_repo() -> SQLiteRepository {
-    SQLiteRepository {
-        connection: Connection::open("./db.sqlite").unwrap(),
+/// Shared by all handlers.
+#[derive(Clone)]
+struct AppState {
+    pool: db::Pool,
+}
+
+impl AppState {
+    /// Run `f` with a pooled connection on a blocking thread, so that SQLite doesn't block the
+    /// runtime.
+    async fn run<T, E, F>(&self, f: F) -> Result<T, E>
+    where
+        F: FnOnce(SQLiteRepository) -> Result<T, E> + Send + 'static,
+        T: Send + 'static,
+        E: From<r2d2::Error> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            f(SQLiteRepository {
+                connection: pool.get()?,
+            })
+        })
+        .await
+        .unwrap()
+    }
+}
+
+/// Lets hyper serve connections from a Unix socket, e.g. behind a reverse proxy.
+struct UnixAccept {
+    listener: tokio::net::UnixListener,
+}
+
+impl hyper::server::accept::Accept for UnixAccept {
+    type Conn = tokio::net::UnixStream;
+    type Error = std::io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<std::result::Result<Self::Conn, Self::Error>>> {
+        match self.listener.poll_accept(cx) {
+            Poll::Ready(Ok((stream, _))) => Poll::Ready(Some(Ok(stream))),
+            Poll::Ready(Err(e)) => Poll::Ready(Some(Err(e))),
+            Poll::Pending => Poll::Pending,
+        }
     }
 }
 
 #[tokio::main]
 async fn main() {
+    let config = match config::Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    let pool = match db::open(config.database) {
+        Ok(pool) => pool,
+        Err(e) => {
+            eprintln!("Can't open the database: {e}");
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = migrations::migrate(&mut pool.get().unwrap()) {
+        eprintln!("Can't migrate the database: {e}");
+        std::process::exit(1);
+    }
+    let state = AppState { pool };
+
+    if let config::Command::ImportRates(paths) = config.command {
+        let repo = SQLiteRepository {
+            connection: state.pool.get().unwrap(),
+        };
+        for path in paths {
+            let rates = std::fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|data| rates::parse_file(&data));
+            match rates {
+                Ok(rates) => match repo.add_exchange_rates(&rates) {
+                    Ok(()) => eprintln!("Imported {} rates from {}.", rates.len(), path.display()),
+                    Err(e) => {
+                        eprintln!("Can't save rates from {}: {e}", path.display());
+                        std::process::exit(1);
+                    }
+                },
+                Err(e) => {
+                    eprintln!("Can't read rates from {}: {e}", path.display());
+                    std::process::exit(1);
+                }
+            }
+        }
+        return;
+    }
+
+    tokio::spawn(recurring::run_scheduler(state.clone()));
+
     let app = Router::new()
+        .nest("/api/v1", api::router())
         .route("/", get(root))
         .route("/own", get(own_transfer))
+        .route("/search", get(search))
         .route("/stats", get(stats))
+        .route("/stats/currency", post(set_reporting_currency))
         .route("/add", post(add_expense))
+        .route("/expense/:id", get(edit_expense).post(update_expense))
+        .route("/expense/:id/delete", post(delete_expense))
         .route("/add_own", post(add_own_transfer))
+        .route("/transfer/:id", get(edit_transfer).post(update_transfer))
+        .route("/transfer/:id/delete", post(delete_transfer))
+        .route("/accounts", get(accounts).post(add_account))
+        .route("/accounts/:id", get(account).post(update_account))
+        .route("/accounts/:id/:direction", post(move_account))
+        .route("/categories", get(categories).post(add_category))
+        .route("/budgets", get(budgets).post(set_budget))
+        .route("/budgets/:id/delete", post(delete_budget))
+        .route("/recurring", get(recurring).post(add_recurring))
+        .route("/recurring/:id/delete", post(delete_recurring))
+        .route("/import", get(import_page).post(import_preview))
+        .route("/import/mapping/:account_id", post(set_import_mapping))
+        .route("/import/confirm", post(import_confirm))
+        .route(
+            "/import/rates",
+            // The full ECB history is several megabytes.
+            post(import_rates).layer(DefaultBodyLimit::max(64 * 1024 * 1024)),
+        )
+        .route("/export", get(export_page))
+        .route("/export/net_worth", get(export_net_worth))
+        .route("/export/:format", get(export))
         .route("/manifest.json", get(manifest))
-        .route("/icon.png", get(icon));
+        .route("/icon.png", get(icon))
+        .fallback(|| async { AppError::NotFound })
+        .with_state(state);
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], 2137));
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
+    match config.unix_socket {
+        Some(path) => {
+            // A socket left over from a previous run would make binding fail.
+            if let Ok(metadata) = std::fs::metadata(&path) {
+                if metadata.file_type().is_socket() {
+                    std::fs::remove_file(&path).unwrap();
+                }
+            }
+            let listener = tokio::net::UnixListener::bind(&path).unwrap();
+            eprintln!("Listening on {}.", path.display());
+            axum::Server::builder(UnixAccept { listener })
+                .serve(app.into_make_service())
+                .await
+                .unwrap();
+        }
+        None => {
+            let addr = SocketAddr::from((config.listen, config.port));
+            eprintln!("Listening on {addr}.");
+            axum::Server::bind(&addr)
+                .serve(app.into_make_service())
+                .await
+                .unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A repository on an empty, migrated database.
+    fn repository() -> SQLiteRepository {
+        let mut connection = db::open_in_memory().get().unwrap();
+        migrations::migrate(&mut connection).unwrap();
+        SQLiteRepository { connection }
+    }
+
+    #[test]
+    fn budget_leaves_out_incomes_and_unknown_rates() {
+        let repo = repository();
+        let jpy = "JPY".parse().unwrap();
+        let pln_account = repo
+            .add_account("Konto".to_string(), Money::new(0, Currency::PLN))
+            .unwrap();
+        let jpy_account = repo
+            .add_account("Yeny".to_string(), Money::new(0, jpy))
+            .unwrap();
+        repo.add_category("Jedzenie".to_string()).unwrap();
+        let category = *repo.get_categories().unwrap().keys().next().unwrap();
+        repo.set_budget(
+            BudgetTarget::Category(category),
+            Money::new(50000, Currency::PLN),
+        )
         .unwrap();
+        for (name, value, account_id) in [
+            ("Zakupy", Money::new(20000, Currency::PLN), pln_account),
+            ("Zwrot", Money::new(-5000, Currency::PLN), pln_account),
+            ("Sushi", Money::new(3000, jpy), jpy_account),
+        ] {
+            repo.add(
+                name.to_string(),
+                value,
+                date(2024, 3, 10),
+                account_id.to_string(),
+                Some(category),
+            )
+            .unwrap();
+        }
+
+        let month = SpecificMonth::from(date(2024, 3, 1));
+        let (statuses, unknown_rates) = get_budget_statuses(&repo, month).unwrap();
+        // The refund doesn't lower the spending, and there's no rate for JPY.
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].spent, Money::new(20000, Currency::PLN));
+        assert_eq!(statuses[0].left, Money::new(30000, Currency::PLN));
+        assert_eq!(unknown_rates, vec![jpy]);
+    }
+
+    fn own_transfer(from: SqliteInteger, value_from: &str, to: SqliteInteger) -> NewOwnTransfer {
+        NewOwnTransfer {
+            account_id_from: from.to_string(),
+            value_from: value_from.to_string(),
+            account_id_to: to.to_string(),
+            value_to: "20".to_string(),
+            date: "2024-03-10".to_string(),
+        }
+    }
+
+    #[test]
+    fn transfer_needs_two_accounts() {
+        let repo = repository();
+        let from = repo
+            .add_account("Konto".to_string(), Money::new(0, Currency::PLN))
+            .unwrap();
+        let to = repo
+            .add_account("Oszczędności".to_string(), Money::new(0, Currency::PLN))
+            .unwrap();
+        let accounts = repo.get_accounts().unwrap();
+        assert!(own_transfer(from, "20", to).validate(&accounts).is_ok());
+        let errors = own_transfer(from, "20", from)
+            .validate(&accounts)
+            .err()
+            .unwrap();
+        assert_eq!(errors.keys().collect::<Vec<_>>(), vec![&"account_id_to"]);
+    }
+
+    #[test]
+    fn transfer_amounts_are_positive() {
+        let repo = repository();
+        let from = repo
+            .add_account("Konto".to_string(), Money::new(0, Currency::PLN))
+            .unwrap();
+        let to = repo
+            .add_account("Oszczędności".to_string(), Money::new(0, Currency::PLN))
+            .unwrap();
+        let accounts = repo.get_accounts().unwrap();
+        for value in ["+20", "0", "-20"] {
+            let errors = own_transfer(from, value, to)
+                .validate(&accounts)
+                .err()
+                .unwrap();
+            assert_eq!(errors.keys().collect::<Vec<_>>(), vec![&"value_from"]);
+        }
+    }
+
+    /// Names of the expenses found by `search`, sorted.
+    fn found(repo: &SQLiteRepository, search: &Search) -> Vec<String> {
+        let mut names = repo
+            .find(search, None, 0)
+            .unwrap()
+            .into_iter()
+            .map(|expense| expense.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(repo.count(search).unwrap(), names.len());
+        names
+    }
+
+    /// An account in each of PLN and JPY, with a few expenses and an income in both.
+    fn search_repository() -> SQLiteRepository {
+        let repo = repository();
+        let jpy = "JPY".parse().unwrap();
+        let pln_account = repo
+            .add_account("Konto".to_string(), Money::new(0, Currency::PLN))
+            .unwrap();
+        let jpy_account = repo
+            .add_account("Yeny".to_string(), Money::new(0, jpy))
+            .unwrap();
+        for (name, value, account_id) in [
+            ("a", Money::new(1250, Currency::PLN), pln_account),
+            ("b", Money::new(1300, Currency::PLN), pln_account),
+            ("c", Money::new(1301, Currency::PLN), pln_account),
+            ("d", Money::new(-1275, Currency::PLN), pln_account),
+            ("e", Money::new(12, jpy), jpy_account),
+            ("f", Money::new(13, jpy), jpy_account),
+            ("g", Money::new(-1300, jpy), jpy_account),
+        ] {
+            repo.add(
+                name.to_string(),
+                value,
+                date(2024, 3, 10),
+                account_id.to_string(),
+                None,
+            )
+            .unwrap();
+        }
+        repo
+    }
+
+    #[test]
+    fn searches_amounts_in_any_currency() {
+        let repo = search_repository();
+        // 12,5 to 13 of the major unit: 1250 to 1300 grosze, but 13 yen.
+        let search = Search {
+            min: Some(12500),
+            max: Some(13000),
+            ..Search::default()
+        };
+        assert_eq!(found(&repo, &search), vec!["a", "b", "d", "f"]);
+        let search = Search {
+            min: Some(13001),
+            ..Search::default()
+        };
+        assert_eq!(found(&repo, &search), vec!["c", "g"]);
+        let search = Search {
+            max: Some(12000),
+            ..Search::default()
+        };
+        assert_eq!(found(&repo, &search), vec!["e"]);
+    }
+
+    #[test]
+    fn searches_incomes_or_expenses() {
+        let repo = search_repository();
+        let search = Search {
+            incomes: Some(true),
+            ..Search::default()
+        };
+        assert_eq!(found(&repo, &search), vec!["d", "g"]);
+        let search = Search {
+            incomes: Some(false),
+            max: Some(13000),
+            ..Search::default()
+        };
+        assert_eq!(found(&repo, &search), vec!["a", "b", "e", "f"]);
+    }
 }
@@ -0,0 +1,292 @@
+use rusqlite::Connection;
+use std::fmt::{Display, Formatter};
+
+/// Embedded schema migrations, applied in order.
+///
+/// The database's `PRAGMA user_version` holds the number of migrations already applied.
+/// Never edit a migration that has been released -- append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: the initial schema. Databases set up by hand before migrations existed already have
+    // these tables (and `user_version` = 0), hence `if not exists`.
+    r#"
+    create table if not exists accounts (
+        id integer primary key,
+        name text not null,
+        currency text not null,
+        display_order integer not null default 0
+    );
+    create table if not exists expenses (
+        name text not null,
+        value numeric not null,
+        date text not null,
+        account_id integer not null references accounts (id)
+    );
+    create table if not exists exchange_rates (
+        currency text primary key,
+        rate real not null
+    );
+    create table if not exists key_value_store (
+        key text primary key,
+        value text not null
+    );
+    "#,
+    // 2: amounts as integer minor units (grosze, cents) instead of floats. Amounts were entered
+    // with at most two decimal places, so rounding recovers them exactly. Also give expenses an
+    // explicit `id` so that their IDs (used in URLs) are stable.
+    r#"
+    create table expenses_new (
+        id integer primary key,
+        name text not null,
+        amount integer not null,
+        date text not null,
+        account_id integer not null references accounts (id)
+    );
+    insert into expenses_new (id, name, amount, date, account_id)
+        select rowid, name, cast(round(value * 100) as integer), date, account_id from expenses;
+    drop table expenses;
+    alter table expenses_new rename to expenses;
+    "#,
+    // 3: optional expense categories.
+    r#"
+    create table categories (
+        id integer primary key,
+        name text not null unique,
+        display_order integer not null default 0
+    );
+    alter table expenses add column category_id integer references categories (id);
+    "#,
+    // 4: monthly budgets, either per category or per account.
+    r#"
+    create table budgets (
+        id integer primary key,
+        category_id integer unique references categories (id),
+        account_id integer unique references accounts (id),
+        amount integer not null,
+        currency text not null,
+        check ((category_id is null) != (account_id is null))
+    );
+    "#,
+    // 5: templates for recurring expenses and incomes. `last_date` is the last date for which
+    // an expense has already been added.
+    r#"
+    create table recurring (
+        id integer primary key,
+        name text not null,
+        amount integer not null,
+        account_id integer not null references accounts (id),
+        category_id integer references categories (id),
+        rule text not null check (rule in ('monthly', 'weekly')),
+        day integer not null,
+        start_date text not null,
+        end_date text,
+        last_date text
+    );
+    "#,
+    // 6: per-account column mappings for importing bank statements, see `import::Mapping`.
+    r#"
+    create table import_mappings (
+        account_id integer primary key references accounts (id),
+        delimiter text not null,
+        skip_rows integer not null,
+        date_column integer not null,
+        date_format text not null,
+        amount_column integer not null,
+        description_column integer not null,
+        negate integer not null
+    );
+    "#,
+    // 7: own transfers, linking the expense on the sending account with the income on the
+    // receiving one. Older transfers were only linked by their description, e.g.
+    // `Przesłanie z "[PLN] A" na "[EUR] B" [1 → 2]`, with both rows added one after the other.
+    r#"
+    create table transfers (
+        id integer primary key,
+        from_expense_id integer not null unique references expenses (id),
+        to_expense_id integer not null unique references expenses (id)
+    );
+    insert into transfers (from_expense_id, to_expense_id)
+        select f.id, t.id from expenses f join expenses t on t.id = f.id + 1
+        where f.name like 'Przesłanie z "%' and t.name = f.name and t.date = f.date
+            and f.name like '%[' || f.account_id || ' → ' || t.account_id || ']';
+    "#,
+    // 8: exchange rates by date, each valid until the next one for the same currency. The
+    // single rate stored so far becomes the oldest known one and so keeps applying to all
+    // past expenses.
+    r#"
+    create table exchange_rates_by_date (
+        currency text not null,
+        date text not null,
+        rate real not null,
+        primary key (currency, date)
+    );
+    insert into exchange_rates_by_date (currency, date, rate)
+        select currency, date('now'), rate from exchange_rates;
+    drop table exchange_rates;
+    alter table exchange_rates_by_date rename to exchange_rates;
+    "#,
+    // 9: account management. Archived accounts keep their history but aren't offered for new
+    // entries. The opening balance (in minor units, positive when there's money on the
+    // account) covers what was there before the first tracked expense.
+    r#"
+    alter table accounts add column archived integer not null default 0;
+    alter table accounts add column opening_balance integer not null default 0;
+    "#,
+    // 10: full-text search of expense names. The index refers to `expenses` instead of keeping
+    // its own copy of the names, and the triggers keep it in sync.
+    r#"
+    create virtual table expenses_fts using fts5 (name, content = 'expenses', content_rowid = 'id');
+    insert into expenses_fts (expenses_fts) values ('rebuild');
+    create trigger expenses_fts_insert after insert on expenses begin
+        insert into expenses_fts (rowid, name) values (new.id, new.name);
+    end;
+    create trigger expenses_fts_delete after delete on expenses begin
+        insert into expenses_fts (expenses_fts, rowid, name) values ('delete', old.id, old.name);
+    end;
+    create trigger expenses_fts_update after update of name on expenses begin
+        insert into expenses_fts (expenses_fts, rowid, name) values ('delete', old.id, old.name);
+        insert into expenses_fts (rowid, name) values (new.id, new.name);
+    end;
+    "#,
+    // 11: the main page reads only the newest months.
+    r#"
+    create index expenses_date on expenses (date);
+    "#,
+];
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Sqlite(rusqlite::Error),
+    DatabaseTooNew { database: usize, supported: usize },
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::Sqlite(e) => write!(f, "SQLite error: {e}"),
+            MigrationError::DatabaseTooNew {
+                database,
+                supported,
+            } => write!(
+                f,
+                "database schema version {database} is newer than the newest one known to this binary ({supported}); refusing to run"
+            ),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for MigrationError {
+    fn from(e: rusqlite::Error) -> Self {
+        MigrationError::Sqlite(e)
+    }
+}
+
+fn get_version(connection: &Connection) -> rusqlite::Result<usize> {
+    connection.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+/// Bring the schema up to date, creating it from scratch for a new database.
+pub fn migrate(connection: &mut Connection) -> Result<(), MigrationError> {
+    let current = get_version(connection)?;
+    if current > MIGRATIONS.len() {
+        return Err(MigrationError::DatabaseTooNew {
+            database: current,
+            supported: MIGRATIONS.len(),
+        });
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(current) {
+        let version = i + 1;
+        // Each migration and its version bump are applied atomically.
+        let tx = connection.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+        eprintln!("Applied database migration {version}.");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amounts(connection: &Connection) -> Vec<(String, i64)> {
+        let mut statement = connection
+            .prepare("select name, amount from expenses order by id")
+            .unwrap();
+        let amounts = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        amounts
+    }
+
+    #[test]
+    fn converts_amounts_to_minor_units() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(MIGRATIONS[0]).unwrap();
+        connection.pragma_update(None, "user_version", 1).unwrap();
+        connection
+            .execute_batch(
+                "insert into accounts (id, name, currency) values (1, 'Konto', 'PLN');
+                insert into expenses (name, value, date, account_id) values
+                    ('a', 21.37, '2022-01-01', 1),
+                    ('b', 0.1, '2022-01-02', 1),
+                    ('c', -5000, '2022-01-03', 1),
+                    ('d', '19.99', '2022-01-04', 1);",
+            )
+            .unwrap();
+
+        migrate(&mut connection).unwrap();
+        assert_eq!(get_version(&connection).unwrap(), MIGRATIONS.len());
+        assert_eq!(
+            amounts(&connection),
+            vec![
+                ("a".to_string(), 2137),
+                ("b".to_string(), 10),
+                ("c".to_string(), -500000),
+                ("d".to_string(), 1999),
+            ]
+        );
+    }
+
+    #[test]
+    fn converts_amounts_stored_as_text() {
+        // Databases set up by hand may have declared the column differently, so that SQLite
+        // kept the amounts as entered.
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "create table accounts (id integer primary key, name text not null, currency text not null, display_order integer not null default 0);
+                create table expenses (name text not null, value text not null, date text not null, account_id integer not null);
+                insert into accounts (id, name, currency) values (1, 'Konto', 'PLN');
+                insert into expenses (name, value, date, account_id) values
+                    ('a', '21.37', '2022-01-01', 1),
+                    ('b', '0.1', '2022-01-02', 1),
+                    ('c', '-5000', '2022-01-03', 1),
+                    ('d', '19.99', '2022-01-04', 1);",
+            )
+            .unwrap();
+        let types: String = connection
+            .query_row(
+                "select group_concat(distinct typeof(value)) from expenses",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(types, "text");
+
+        migrate(&mut connection).unwrap();
+        assert_eq!(
+            amounts(&connection),
+            vec![
+                ("a".to_string(), 2137),
+                ("b".to_string(), 10),
+                ("c".to_string(), -500000),
+                ("d".to_string(), 1999),
+            ]
+        );
+    }
+}
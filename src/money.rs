@@ -0,0 +1,579 @@
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef};
+use rusqlite::{Result, ToSql};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::Neg;
+use std::str::FromStr;
+
+/// An ISO 4217 currency, e.g. `PLN`. All of them are built in, see `CURRENCIES`.
+#[derive(Clone, Copy)]
+pub struct Currency(&'static CurrencyInfo);
+
+struct CurrencyInfo {
+    code: &'static str,
+    /// Digits after the decimal point: 2 for cents, 0 for yen, 3 for fils.
+    minor_digits: u32,
+    /// Shown instead of the code, if there's an unambiguous one.
+    symbol: Option<&'static str>,
+    /// Whether the symbol goes before the amount.
+    symbol_first: bool,
+}
+
+impl CurrencyInfo {
+    const fn new(code: &'static str, minor_digits: u32) -> Self {
+        CurrencyInfo {
+            code,
+            minor_digits,
+            symbol: None,
+            symbol_first: false,
+        }
+    }
+
+    const fn with_symbol(self, symbol: &'static str, symbol_first: bool) -> Self {
+        CurrencyInfo {
+            symbol: Some(symbol),
+            symbol_first,
+            ..self
+        }
+    }
+}
+
+/// Active ISO 4217 currencies, without funds and precious metals. Sorted by code.
+const CURRENCIES: &[CurrencyInfo] = &[
+    CurrencyInfo::new("AED", 2),
+    CurrencyInfo::new("AFN", 2),
+    CurrencyInfo::new("ALL", 2),
+    CurrencyInfo::new("AMD", 2),
+    CurrencyInfo::new("ANG", 2),
+    CurrencyInfo::new("AOA", 2),
+    CurrencyInfo::new("ARS", 2),
+    CurrencyInfo::new("AUD", 2),
+    CurrencyInfo::new("AWG", 2),
+    CurrencyInfo::new("AZN", 2),
+    CurrencyInfo::new("BAM", 2),
+    CurrencyInfo::new("BBD", 2),
+    CurrencyInfo::new("BDT", 2),
+    CurrencyInfo::new("BGN", 2),
+    CurrencyInfo::new("BHD", 3),
+    CurrencyInfo::new("BIF", 0),
+    CurrencyInfo::new("BMD", 2),
+    CurrencyInfo::new("BND", 2),
+    CurrencyInfo::new("BOB", 2),
+    CurrencyInfo::new("BRL", 2),
+    CurrencyInfo::new("BSD", 2),
+    CurrencyInfo::new("BTN", 2),
+    CurrencyInfo::new("BWP", 2),
+    CurrencyInfo::new("BYN", 2),
+    CurrencyInfo::new("BZD", 2),
+    CurrencyInfo::new("CAD", 2),
+    CurrencyInfo::new("CDF", 2),
+    CurrencyInfo::new("CHF", 2),
+    CurrencyInfo::new("CLP", 0),
+    CurrencyInfo::new("CNY", 2),
+    CurrencyInfo::new("COP", 2),
+    CurrencyInfo::new("CRC", 2),
+    CurrencyInfo::new("CUP", 2),
+    CurrencyInfo::new("CVE", 2),
+    CurrencyInfo::new("CZK", 2).with_symbol(" Kč", false),
+    CurrencyInfo::new("DJF", 0),
+    CurrencyInfo::new("DKK", 2),
+    CurrencyInfo::new("DOP", 2),
+    CurrencyInfo::new("DZD", 2),
+    CurrencyInfo::new("EGP", 2),
+    CurrencyInfo::new("ERN", 2),
+    CurrencyInfo::new("ETB", 2),
+    CurrencyInfo::new("EUR", 2).with_symbol("€", true),
+    CurrencyInfo::new("FJD", 2),
+    CurrencyInfo::new("FKP", 2),
+    CurrencyInfo::new("GBP", 2).with_symbol("£", true),
+    CurrencyInfo::new("GEL", 2),
+    CurrencyInfo::new("GHS", 2),
+    CurrencyInfo::new("GIP", 2),
+    CurrencyInfo::new("GMD", 2),
+    CurrencyInfo::new("GNF", 0),
+    CurrencyInfo::new("GTQ", 2),
+    CurrencyInfo::new("GYD", 2),
+    CurrencyInfo::new("HKD", 2),
+    CurrencyInfo::new("HNL", 2),
+    CurrencyInfo::new("HTG", 2),
+    CurrencyInfo::new("HUF", 2).with_symbol(" Ft", false),
+    CurrencyInfo::new("IDR", 2),
+    CurrencyInfo::new("ILS", 2).with_symbol("₪", true),
+    CurrencyInfo::new("INR", 2).with_symbol("₹", true),
+    CurrencyInfo::new("IQD", 3),
+    CurrencyInfo::new("IRR", 2),
+    CurrencyInfo::new("ISK", 0),
+    CurrencyInfo::new("JMD", 2),
+    CurrencyInfo::new("JOD", 3),
+    CurrencyInfo::new("JPY", 0).with_symbol("¥", true),
+    CurrencyInfo::new("KES", 2),
+    CurrencyInfo::new("KGS", 2),
+    CurrencyInfo::new("KHR", 2),
+    CurrencyInfo::new("KMF", 0),
+    CurrencyInfo::new("KPW", 2),
+    CurrencyInfo::new("KRW", 0).with_symbol("₩", true),
+    CurrencyInfo::new("KWD", 3),
+    CurrencyInfo::new("KYD", 2),
+    CurrencyInfo::new("KZT", 2),
+    CurrencyInfo::new("LAK", 2),
+    CurrencyInfo::new("LBP", 2),
+    CurrencyInfo::new("LKR", 2),
+    CurrencyInfo::new("LRD", 2),
+    CurrencyInfo::new("LSL", 2),
+    CurrencyInfo::new("LYD", 3),
+    CurrencyInfo::new("MAD", 2),
+    CurrencyInfo::new("MDL", 2),
+    CurrencyInfo::new("MGA", 2),
+    CurrencyInfo::new("MKD", 2),
+    CurrencyInfo::new("MMK", 2),
+    CurrencyInfo::new("MNT", 2),
+    CurrencyInfo::new("MOP", 2),
+    CurrencyInfo::new("MRU", 2),
+    CurrencyInfo::new("MUR", 2),
+    CurrencyInfo::new("MVR", 2),
+    CurrencyInfo::new("MWK", 2),
+    CurrencyInfo::new("MXN", 2),
+    CurrencyInfo::new("MYR", 2),
+    CurrencyInfo::new("MZN", 2),
+    CurrencyInfo::new("NAD", 2),
+    CurrencyInfo::new("NGN", 2),
+    CurrencyInfo::new("NIO", 2),
+    CurrencyInfo::new("NOK", 2),
+    CurrencyInfo::new("NPR", 2),
+    CurrencyInfo::new("NZD", 2),
+    CurrencyInfo::new("OMR", 3),
+    CurrencyInfo::new("PAB", 2),
+    CurrencyInfo::new("PEN", 2),
+    CurrencyInfo::new("PGK", 2),
+    CurrencyInfo::new("PHP", 2),
+    CurrencyInfo::new("PKR", 2),
+    CurrencyInfo::new("PLN", 2).with_symbol(" zł", false),
+    CurrencyInfo::new("PYG", 0),
+    CurrencyInfo::new("QAR", 2),
+    CurrencyInfo::new("RON", 2),
+    CurrencyInfo::new("RSD", 2),
+    CurrencyInfo::new("RUB", 2),
+    CurrencyInfo::new("RWF", 0),
+    CurrencyInfo::new("SAR", 2),
+    CurrencyInfo::new("SBD", 2),
+    CurrencyInfo::new("SCR", 2),
+    CurrencyInfo::new("SDG", 2),
+    CurrencyInfo::new("SEK", 2),
+    CurrencyInfo::new("SGD", 2),
+    CurrencyInfo::new("SHP", 2),
+    CurrencyInfo::new("SLE", 2),
+    CurrencyInfo::new("SOS", 2),
+    CurrencyInfo::new("SRD", 2),
+    CurrencyInfo::new("SSP", 2),
+    CurrencyInfo::new("STN", 2),
+    CurrencyInfo::new("SVC", 2),
+    CurrencyInfo::new("SYP", 2),
+    CurrencyInfo::new("SZL", 2),
+    CurrencyInfo::new("THB", 2),
+    CurrencyInfo::new("TJS", 2),
+    CurrencyInfo::new("TMT", 2),
+    CurrencyInfo::new("TND", 3),
+    CurrencyInfo::new("TOP", 2),
+    CurrencyInfo::new("TRY", 2),
+    CurrencyInfo::new("TTD", 2),
+    CurrencyInfo::new("TWD", 2),
+    CurrencyInfo::new("TZS", 2),
+    CurrencyInfo::new("UAH", 2).with_symbol(" ₴", false),
+    CurrencyInfo::new("UGX", 0),
+    CurrencyInfo::new("USD", 2).with_symbol("$", true),
+    CurrencyInfo::new("UYU", 2),
+    CurrencyInfo::new("UZS", 2),
+    CurrencyInfo::new("VED", 2),
+    CurrencyInfo::new("VES", 2),
+    CurrencyInfo::new("VND", 0),
+    CurrencyInfo::new("VUV", 0),
+    CurrencyInfo::new("WST", 2),
+    CurrencyInfo::new("XAF", 0),
+    CurrencyInfo::new("XCD", 2),
+    CurrencyInfo::new("XCG", 2),
+    CurrencyInfo::new("XOF", 0),
+    CurrencyInfo::new("XPF", 0),
+    CurrencyInfo::new("YER", 2),
+    CurrencyInfo::new("ZAR", 2),
+    CurrencyInfo::new("ZMW", 2),
+    CurrencyInfo::new("ZWG", 2),
+];
+
+impl Currency {
+    pub const EUR: Currency = Currency::known("EUR");
+    pub const PLN: Currency = Currency::known("PLN");
+
+    const fn known(code: &str) -> Currency {
+        let mut i = 0;
+        while i < CURRENCIES.len() {
+            let candidate = CURRENCIES[i].code.as_bytes();
+            let code = code.as_bytes();
+            if candidate[0] == code[0] && candidate[1] == code[1] && candidate[2] == code[2] {
+                return Currency(&CURRENCIES[i]);
+            }
+            i += 1;
+        }
+        panic!("unknown currency");
+    }
+
+    pub fn all() -> impl Iterator<Item = Currency> {
+        CURRENCIES.iter().map(Currency)
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.0.code
+    }
+
+    pub fn minor_digits(&self) -> u32 {
+        self.0.minor_digits
+    }
+
+    /// Number of minor units (grosze, cents, pence) in a major unit.
+    fn minor_units(&self) -> i64 {
+        10_i64.pow(self.0.minor_digits)
+    }
+}
+
+impl PartialEq for Currency {
+    fn eq(&self, other: &Self) -> bool {
+        self.code() == other.code()
+    }
+}
+
+impl Eq for Currency {}
+
+impl Hash for Currency {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.code().hash(state)
+    }
+}
+
+impl PartialOrd for Currency {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Currency {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.code().cmp(other.code())
+    }
+}
+
+impl Display for Currency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl Debug for Currency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl ToSql for Currency {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.code()))
+    }
+}
+
+impl FromSql for Currency {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|e: String| FromSqlError::Other(e.into()))
+    }
+}
+
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(code: &str) -> std::result::Result<Self, Self::Err> {
+        CURRENCIES
+            .binary_search_by(|info| info.code.cmp(code))
+            .map(|i| Currency(&CURRENCIES[i]))
+            .map_err(|_| format!("unknown currency {code:?}"))
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A fixed-point amount of money, stored as an integer number of minor units. Expenses are
+/// positive and incomes are negative, in the database as well as in exports and the API.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Money {
+    pub amount: i64,
+    pub currency: Currency,
+}
+
+impl Money {
+    pub fn new(amount: i64, currency: Currency) -> Self {
+        Money { amount, currency }
+    }
+
+    /// Only for approximations (e.g. currency conversion), never for bookkeeping.
+    pub fn approx_major_units(&self) -> f64 {
+        self.amount as f64 / self.currency.minor_units() as f64
+    }
+
+    /// Rounded to whole major units, e.g. for totals converted between currencies.
+    pub fn rounded(&self) -> Money {
+        let whole = self.approx_major_units().round() as i64;
+        Money::new(whole * self.currency.minor_units(), self.currency)
+    }
+
+    /// The amount for machines rather than people, e.g. "-21.37".
+    pub fn decimal(&self) -> String {
+        self.input_value().replace(',', ".")
+    }
+
+    /// The amount as typed into our forms, e.g. "-21,37".
+    pub fn input_value(&self) -> String {
+        let sign = if self.amount < 0 { "-" } else { "" };
+        let (major, minor) = self.split();
+        match self.currency.minor_digits() {
+            0 => format!("{sign}{major}"),
+            digits => format!("{sign}{major},{minor:0digits$}", digits = digits as usize),
+        }
+    }
+
+    /// Absolute major and minor units.
+    fn split(&self) -> (u64, u64) {
+        let abs = self.amount.unsigned_abs();
+        let minor_units = self.currency.minor_units() as u64;
+        (abs / minor_units, abs % minor_units)
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Self::Output {
+        Money::new(-self.amount, self.currency)
+    }
+}
+
+impl Display for Money {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let sign = if self.amount < 0 { "-" } else { "" };
+        let number = match self.split() {
+            (major, 0) => format!("{major}"),
+            (major, minor) => format!(
+                "{major}.{minor:0digits$}",
+                digits = self.currency.minor_digits() as usize
+            ),
+        };
+        match (self.currency.0.symbol, self.currency.0.symbol_first) {
+            (Some(symbol), true) => write!(f, "{sign}{symbol}{number}"),
+            (Some(symbol), false) => write!(f, "{sign}{number}{symbol}"),
+            (None, _) => write!(f, "{sign}{number} {}", self.currency),
+        }
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // We use `Serialize` only for templating, let's reuse `Display`.
+        serializer.serialize_str(format!("{}", self).as_str())
+    }
+}
+
+/// Why an amount couldn't be parsed. `Display` gives a message for the user.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AmountError {
+    Empty,
+    Invalid,
+    /// More digits after the decimal separator than the currency has.
+    TooManyDecimals(u32),
+    TooLarge,
+}
+
+impl Display for AmountError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AmountError::Empty => "Podaj kwotę.",
+            AmountError::Invalid => "Niepoprawna kwota, np. 21,37.",
+            AmountError::TooManyDecimals(0) => "Ta waluta nie ma części ułamkowych.",
+            AmountError::TooManyDecimals(1) => "Najwyżej jedna cyfra po przecinku.",
+            AmountError::TooManyDecimals(2) => "Najwyżej dwie cyfry po przecinku.",
+            AmountError::TooManyDecimals(3) => "Najwyżej trzy cyfry po przecinku.",
+            AmountError::TooManyDecimals(_) => "Za dużo cyfr po przecinku.",
+            AmountError::TooLarge => "Za duża kwota.",
+        })
+    }
+}
+
+impl std::error::Error for AmountError {}
+
+/// Parse a number like "21,37", "1 234.5" or "-20" into minor units of a currency with the
+/// given `minor_digits`. Both a comma and a dot can be the decimal separator, and spaces can
+/// group thousands.
+pub fn parse_minor_units(value: &str, minor_digits: u32) -> std::result::Result<i64, AmountError> {
+    let value = value
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '\u{a0}' | '\u{202f}'))
+        .collect::<String>();
+    if value.is_empty() {
+        return Err(AmountError::Empty);
+    }
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(&value)),
+    };
+    let (major, minor) = match value.find([',', '.']) {
+        Some(i) => (&value[..i], Some(&value[i + 1..])),
+        None => (value, None),
+    };
+    let is_number = |digits: &str| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit());
+    if !is_number(major) || !minor.is_none_or(is_number) {
+        return Err(AmountError::Invalid);
+    }
+    let minor = minor.unwrap_or("");
+    if minor.len() > minor_digits as usize {
+        return Err(AmountError::TooManyDecimals(minor_digits));
+    }
+    let major: i64 = major.parse().map_err(|_| AmountError::TooLarge)?;
+    let minor: i64 = format!("{minor:0<digits$}", digits = minor_digits as usize)
+        .parse()
+        .unwrap_or(0);
+    let amount = major
+        .checked_mul(10_i64.pow(minor_digits))
+        .and_then(|amount| amount.checked_add(minor))
+        .ok_or(AmountError::TooLarge)?;
+    Ok(if negative { -amount } else { amount })
+}
+
+/// Parse an amount typed into a form. Expenses are positive, while incomes can be typed
+/// either with a plus, like they're displayed ("+20"), or with a minus, like they're stored.
+pub fn parse_amount(value: &str, minor_digits: u32) -> std::result::Result<i64, AmountError> {
+    let amount = parse_minor_units(value, minor_digits)?;
+    Ok(if value.trim_start().starts_with('+') {
+        -amount
+    } else {
+        amount
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_decimal_separators() {
+        assert_eq!(parse_amount("21,37", 2), Ok(2137));
+        assert_eq!(parse_amount("21.37", 2), Ok(2137));
+        assert_eq!(parse_amount("1234.5", 2), Ok(123450));
+        assert_eq!(parse_amount("0,05", 2), Ok(5));
+        assert_eq!(parse_amount("20", 2), Ok(2000));
+    }
+
+    #[test]
+    fn parses_grouped_thousands() {
+        assert_eq!(parse_amount("1 234,56", 2), Ok(123456));
+        assert_eq!(parse_amount("1\u{a0}234,56", 2), Ok(123456));
+        assert_eq!(parse_amount("1\u{202f}234 567", 2), Ok(123456700));
+        assert_eq!(parse_amount("  21,37 ", 2), Ok(2137));
+    }
+
+    #[test]
+    fn parses_incomes() {
+        assert_eq!(parse_amount("+20", 2), Ok(-2000));
+        assert_eq!(parse_amount("-20", 2), Ok(-2000));
+        assert_eq!(parse_amount(" +1 234,5", 2), Ok(-123450));
+        // Outside of forms a plus is just a plus.
+        assert_eq!(parse_minor_units("+20", 2), Ok(2000));
+        assert_eq!(parse_minor_units("-20", 2), Ok(-2000));
+    }
+
+    #[test]
+    fn rejects_empty_values() {
+        assert_eq!(parse_amount("", 2), Err(AmountError::Empty));
+        assert_eq!(parse_amount("   ", 2), Err(AmountError::Empty));
+    }
+
+    #[test]
+    fn rejects_too_many_decimals() {
+        assert_eq!(
+            parse_amount("21,375", 2),
+            Err(AmountError::TooManyDecimals(2))
+        );
+        assert_eq!(
+            parse_amount("1.234", 2),
+            Err(AmountError::TooManyDecimals(2))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        for value in [
+            "abc", "-", "+", "+-20", "--20", ",5", "5,", "1,2,3", "1.2,3", "2e3",
+        ] {
+            assert_eq!(
+                parse_amount(value, 2),
+                Err(AmountError::Invalid),
+                "{value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_other_minor_digits() {
+        assert_eq!(parse_amount("1 500", 0), Ok(1500));
+        assert_eq!(parse_amount("1,5", 0), Err(AmountError::TooManyDecimals(0)));
+        assert_eq!(parse_amount("1,234", 3), Ok(1234));
+        assert_eq!(parse_amount("1,2", 3), Ok(1200));
+    }
+
+    #[test]
+    fn rejects_overflow() {
+        assert_eq!(
+            parse_amount("92233720368547759", 2),
+            Err(AmountError::TooLarge)
+        );
+        assert_eq!(
+            parse_amount("99999999999999999999", 2),
+            Err(AmountError::TooLarge)
+        );
+    }
+
+    #[test]
+    fn currencies_are_sorted_and_unique() {
+        assert!(CURRENCIES.windows(2).all(|w| w[0].code < w[1].code));
+    }
+
+    #[test]
+    fn formats_by_currency() {
+        let money = |amount, code: &str| Money::new(amount, code.parse().unwrap());
+        assert_eq!(money(-2137, "PLN").to_string(), "-21.37 zł");
+        assert_eq!(money(2000, "EUR").to_string(), "€20");
+        assert_eq!(money(150, "CHF").to_string(), "1.50 CHF");
+        assert_eq!(money(1500, "JPY").to_string(), "¥1500");
+        assert_eq!(money(1005, "KWD").input_value(), "1,005");
+        assert_eq!(money(-1500, "JPY").input_value(), "-1500");
+    }
+}
@@ -0,0 +1,414 @@
+use crate::money::{Currency, Money};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Value of one unit of each currency in EUR over time, loaded from `exchange_rates` once per
+/// page so that converting many expenses doesn't query the database for each of them. Other
+/// conversions go through EUR.
+pub struct ExchangeRates {
+    /// Sorted by date.
+    history: HashMap<Currency, Vec<(NaiveDate, f64)>>,
+}
+
+impl ExchangeRates {
+    pub fn new(rates: impl IntoIterator<Item = (Currency, NaiveDate, f64)>) -> Self {
+        let mut history: HashMap<Currency, Vec<(NaiveDate, f64)>> = HashMap::new();
+        for (currency, date, rate) in rates {
+            history.entry(currency).or_default().push((date, rate));
+        }
+        for rates in history.values_mut() {
+            rates.sort_by_key(|(date, _)| *date);
+        }
+        ExchangeRates { history }
+    }
+
+    /// The rate valid on `date`: the latest one from that day or before. Dates older than the
+    /// whole history use the oldest known rate. `None` if there are no rates for `currency`.
+    pub fn to_eur(&self, currency: Currency, date: NaiveDate) -> Option<f64> {
+        if currency == Currency::EUR {
+            return Some(1.0);
+        }
+        match self.history.get(&currency) {
+            Some(rates) if !rates.is_empty() => {
+                let newer = rates.partition_point(|(valid_from, _)| *valid_from <= date);
+                Some(rates[newer.saturating_sub(1)].1)
+            }
+            _ => fallback_to_eur(currency),
+        }
+    }
+
+    /// Whether there's any rate for `currency`, so that it can be converted.
+    pub fn knows(&self, currency: Currency) -> bool {
+        currency == Currency::EUR
+            || self.history.contains_key(&currency)
+            || fallback_to_eur(currency).is_some()
+    }
+
+    /// `money` in `to` at the rates valid on `date`, if they're known.
+    pub fn convert(&self, money: Money, to: Currency, date: NaiveDate) -> Option<Money> {
+        if money.currency == to {
+            return Some(money);
+        }
+        let rate = self.to_eur(money.currency, date)? / self.to_eur(to, date)?;
+        let major_units = money.approx_major_units() * rate;
+        let minor_units = 10_f64.powi(to.minor_digits() as i32);
+        Some(Money::new((major_units * minor_units).round() as i64, to))
+    }
+}
+
+/// Hardcoded exchange rates for the currencies casa started with, used until real ones are
+/// imported.
+fn fallback_to_eur(currency: Currency) -> Option<f64> {
+    match currency.code() {
+        "PLN" => Some(0.23),
+        "USD" => Some(0.92),
+        "GBP" => Some(1.18),
+        _ => None,
+    }
+}
+
+/// Rates published for one day, as the price of one unit of the base currency (ECB: one EUR
+/// in the listed currency) or of the listed currency (NBP: in PLN).
+struct Table {
+    date: NaiveDate,
+    quotes: Vec<(String, f64)>,
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("niepoprawna data {date:?}"))
+}
+
+fn parse_rate(rate: &str) -> Result<f64, String> {
+    rate.trim()
+        .parse()
+        .map_err(|_| format!("niepoprawny kurs {rate:?}"))
+}
+
+/// Reads the rate history from an ECB `eurofxref-hist` file (CSV or XML) or NBP table A
+/// (JSON or XML), recognized by their content. Codes which aren't ISO 4217 currencies are
+/// skipped.
+pub fn parse_file(data: &[u8]) -> Result<Vec<(Currency, NaiveDate, f64)>, String> {
+    let text = std::str::from_utf8(data).map_err(|_| "plik nie jest w UTF-8".to_string())?;
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    if text.starts_with('[') || text.starts_with('{') {
+        from_nbp(parse_nbp_json(text)?)
+    } else if text.starts_with('<') {
+        let document = roxmltree::Document::parse(text).map_err(|e| e.to_string())?;
+        let root = document.root_element();
+        match root.tag_name().name() {
+            "Envelope" => Ok(from_ecb(parse_ecb_xml(root)?)),
+            "ArrayOfExchangeRatesTable" | "ExchangeRatesTable" => from_nbp(parse_nbp_xml(root)?),
+            other => Err(format!("nieznany format XML: <{other}>")),
+        }
+    } else {
+        Ok(from_ecb(parse_ecb_csv(text)?))
+    }
+}
+
+/// ECB publishes how much of each currency one EUR buys.
+fn from_ecb(tables: Vec<Table>) -> Vec<(Currency, NaiveDate, f64)> {
+    tables
+        .into_iter()
+        .flat_map(|table| {
+            let date = table.date;
+            table.quotes.into_iter().filter_map(move |(code, rate)| {
+                let currency = code.parse().ok()?;
+                (rate > 0.0).then(|| (currency, date, 1.0 / rate))
+            })
+        })
+        .collect()
+}
+
+/// NBP publishes prices in PLN, so they're converted to EUR with that day's EUR price.
+fn from_nbp(tables: Vec<Table>) -> Result<Vec<(Currency, NaiveDate, f64)>, String> {
+    let mut rates = Vec::new();
+    for table in tables {
+        let eur = table
+            .quotes
+            .iter()
+            .find(|(code, _)| code == "EUR")
+            .map(|(_, rate)| *rate)
+            .filter(|rate| *rate > 0.0)
+            .ok_or(format!("brak kursu EUR w tabeli z {}", table.date))?;
+        rates.push((Currency::PLN, table.date, 1.0 / eur));
+        for (code, rate) in &table.quotes {
+            match code.parse() {
+                Ok(currency) if currency != Currency::EUR => {
+                    rates.push((currency, table.date, rate / eur))
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(rates)
+}
+
+/// `Date,USD,JPY,...` followed by a row per day, with `N/A` for missing rates.
+fn parse_ecb_csv(text: &str) -> Result<Vec<Table>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(text.as_bytes());
+    let codes = reader
+        .headers()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(|code| code.trim().to_string())
+        .collect::<Vec<_>>();
+    if codes.first().map(String::as_str) != Some("Date") {
+        return Err("nieznany format pliku, oczekiwano eurofxref-hist.csv z EBC".to_string());
+    }
+    let mut tables = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        let mut fields = record.iter();
+        let date = parse_date(fields.next().unwrap_or_default())?;
+        let mut quotes = Vec::new();
+        for (code, rate) in codes[1..].iter().zip(fields) {
+            let rate = rate.trim();
+            if !code.is_empty() && !rate.is_empty() && rate != "N/A" {
+                quotes.push((code.clone(), parse_rate(rate)?));
+            }
+        }
+        tables.push(Table { date, quotes });
+    }
+    Ok(tables)
+}
+
+/// `<Cube time="..."><Cube currency="USD" rate="..."/>...</Cube>` inside the envelope.
+fn parse_ecb_xml(root: roxmltree::Node) -> Result<Vec<Table>, String> {
+    root.descendants()
+        .filter(|node| node.has_tag_name("Cube"))
+        .filter_map(|node| Some((node, node.attribute("time")?)))
+        .map(|(node, date)| {
+            let quotes = node
+                .children()
+                .filter_map(|cube| Some((cube.attribute("currency")?, cube.attribute("rate")?)))
+                .map(|(code, rate)| Ok((code.to_string(), parse_rate(rate)?)))
+                .collect::<Result<_, String>>()?;
+            Ok(Table {
+                date: parse_date(date)?,
+                quotes,
+            })
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NbpJson {
+    Tables(Vec<NbpTable>),
+    Table(NbpTable),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NbpTable {
+    effective_date: String,
+    rates: Vec<NbpRate>,
+}
+
+#[derive(Deserialize)]
+struct NbpRate {
+    code: String,
+    mid: f64,
+}
+
+/// What `api.nbp.pl/api/exchangerates/tables/A` returns, one or more days at a time.
+fn parse_nbp_json(text: &str) -> Result<Vec<Table>, String> {
+    let tables = match serde_json::from_str(text).map_err(|e| e.to_string())? {
+        NbpJson::Tables(tables) => tables,
+        NbpJson::Table(table) => vec![table],
+    };
+    tables
+        .into_iter()
+        .map(|table| {
+            Ok(Table {
+                date: parse_date(&table.effective_date)?,
+                quotes: table
+                    .rates
+                    .into_iter()
+                    .map(|rate| (rate.code, rate.mid))
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// The XML version of `parse_nbp_json`, with `<ExchangeRatesTable>` elements.
+fn parse_nbp_xml(root: roxmltree::Node) -> Result<Vec<Table>, String> {
+    let child_text = |node: roxmltree::Node, name: &str| {
+        node.children()
+            .find(|child| child.has_tag_name(name))
+            .and_then(|child| child.text())
+            .map(str::to_string)
+            .ok_or(format!("brak elementu <{name}>"))
+    };
+    root.descendants()
+        .filter(|node| node.has_tag_name("ExchangeRatesTable"))
+        .map(|table| {
+            let quotes = table
+                .descendants()
+                .filter(|node| node.has_tag_name("Rate"))
+                .map(|rate| {
+                    Ok((
+                        child_text(rate, "Code")?,
+                        parse_rate(&child_text(rate, "Mid")?)?,
+                    ))
+                })
+                .collect::<Result<_, String>>()?;
+            Ok(Table {
+                date: parse_date(&child_text(table, "EffectiveDate")?)?,
+                quotes,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date;
+
+    fn rate_of(rates: &[(Currency, NaiveDate, f64)], code: &str, on: NaiveDate) -> Option<f64> {
+        rates
+            .iter()
+            .find(|(currency, date, _)| currency.code() == code && *date == on)
+            .map(|(_, _, rate)| *rate)
+    }
+
+    fn assert_close(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("missing rate");
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn parses_ecb_csv() {
+        // The real file ends every line with a comma and has N/A for days without a rate.
+        let data = "Date,USD,JPY,XYZ,PLN,\n2024-03-01,1.0812,162.5,1.0,4.3123,\n2024-02-29,N/A,161.8,1.0,4.32,\n";
+        let rates = parse_file(data.as_bytes()).unwrap();
+        assert_close(rate_of(&rates, "USD", date(2024, 3, 1)), 1.0 / 1.0812);
+        assert_close(rate_of(&rates, "PLN", date(2024, 2, 29)), 1.0 / 4.32);
+        assert_close(rate_of(&rates, "JPY", date(2024, 2, 29)), 1.0 / 161.8);
+        assert_eq!(rate_of(&rates, "USD", date(2024, 2, 29)), None);
+        // Not a currency.
+        assert!(rates
+            .iter()
+            .all(|(currency, _, _)| currency.code() != "XYZ"));
+        assert_eq!(rates.len(), 5);
+    }
+
+    #[test]
+    fn rejects_unknown_csv() {
+        assert!(parse_file(b"Datum,USD\n2024-03-01,1.08\n").is_err());
+        assert!(parse_file(b"Date,USD\n01.03.2024,1.08\n").is_err());
+        assert!(parse_file(b"Date,USD\n2024-03-01,abc\n").is_err());
+    }
+
+    #[test]
+    fn parses_ecb_xml() {
+        let data = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref">
+    <gesmes:subject>Reference rates</gesmes:subject>
+    <Cube>
+        <Cube time="2024-03-01">
+            <Cube currency="USD" rate="1.0812"/>
+            <Cube currency="PLN" rate="4.3123"/>
+        </Cube>
+        <Cube time="2024-02-29">
+            <Cube currency="USD" rate="1.0813"/>
+        </Cube>
+    </Cube>
+</gesmes:Envelope>"#;
+        let rates = parse_file(data.as_bytes()).unwrap();
+        assert_close(rate_of(&rates, "USD", date(2024, 3, 1)), 1.0 / 1.0812);
+        assert_close(rate_of(&rates, "PLN", date(2024, 3, 1)), 1.0 / 4.3123);
+        assert_close(rate_of(&rates, "USD", date(2024, 2, 29)), 1.0 / 1.0813);
+        assert_eq!(rates.len(), 3);
+    }
+
+    #[test]
+    fn parses_nbp_json() {
+        let data = r#"[{"table":"A","no":"042/A/NBP/2024","effectiveDate":"2024-03-01","rates":[
+            {"currency":"dolar amerykański","code":"USD","mid":3.9803},
+            {"currency":"euro","code":"EUR","mid":4.3123},
+            {"currency":"frank szwajcarski","code":"CHF","mid":4.5091}
+        ]}]"#;
+        let rates = parse_file(data.as_bytes()).unwrap();
+        let day = date(2024, 3, 1);
+        // NBP prices are in PLN, so EUR is worth 4.3123 PLN and PLN 1/4.3123 EUR.
+        assert_close(rate_of(&rates, "PLN", day), 1.0 / 4.3123);
+        assert_close(rate_of(&rates, "USD", day), 3.9803 / 4.3123);
+        assert_close(rate_of(&rates, "CHF", day), 4.5091 / 4.3123);
+        assert_eq!(rate_of(&rates, "EUR", day), None);
+        assert_eq!(rates.len(), 3);
+    }
+
+    #[test]
+    fn parses_single_nbp_json_table() {
+        let data = r#"{"effectiveDate":"2024-03-04","rates":[{"code":"EUR","mid":4.3},{"code":"USD","mid":4.0}]}"#;
+        let rates = parse_file(data.as_bytes()).unwrap();
+        assert_close(rate_of(&rates, "USD", date(2024, 3, 4)), 4.0 / 4.3);
+    }
+
+    #[test]
+    fn parses_nbp_xml() {
+        let data = r#"<?xml version="1.0" encoding="utf-8"?>
+<ArrayOfExchangeRatesTable xmlns:xsd="http://www.w3.org/2001/XMLSchema">
+    <ExchangeRatesTable>
+        <Table>A</Table>
+        <EffectiveDate>2024-03-01</EffectiveDate>
+        <Rates>
+            <Rate><Currency>dolar amerykański</Currency><Code>USD</Code><Mid>3.9803</Mid></Rate>
+            <Rate><Currency>euro</Currency><Code>EUR</Code><Mid>4.3123</Mid></Rate>
+        </Rates>
+    </ExchangeRatesTable>
+</ArrayOfExchangeRatesTable>"#;
+        let rates = parse_file(data.as_bytes()).unwrap();
+        let day = date(2024, 3, 1);
+        assert_close(rate_of(&rates, "PLN", day), 1.0 / 4.3123);
+        assert_close(rate_of(&rates, "USD", day), 3.9803 / 4.3123);
+        assert_eq!(rates.len(), 2);
+    }
+
+    #[test]
+    fn rejects_nbp_table_without_eur() {
+        let json = r#"[{"effectiveDate":"2024-03-01","rates":[{"code":"USD","mid":3.9803}]}]"#;
+        assert_eq!(
+            parse_file(json.as_bytes()).err(),
+            Some("brak kursu EUR w tabeli z 2024-03-01".to_string())
+        );
+        let xml = r#"<ExchangeRatesTable><EffectiveDate>2024-03-01</EffectiveDate><Rates>
+            <Rate><Code>USD</Code><Mid>3.9803</Mid></Rate>
+        </Rates></ExchangeRatesTable>"#;
+        assert!(parse_file(xml.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn converts_through_eur() {
+        let rates = ExchangeRates::new([
+            (Currency::PLN, date(2024, 1, 1), 0.25),
+            (Currency::PLN, date(2024, 3, 1), 0.2),
+        ]);
+        let money = Money::new(1000, Currency::EUR);
+        assert_eq!(
+            rates.convert(money, Currency::PLN, date(2024, 2, 1)),
+            Some(Money::new(4000, Currency::PLN))
+        );
+        assert_eq!(
+            rates.convert(money, Currency::PLN, date(2024, 3, 1)),
+            Some(Money::new(5000, Currency::PLN))
+        );
+        // Older than the history.
+        assert_eq!(
+            rates.convert(money, Currency::PLN, date(2023, 1, 1)),
+            Some(Money::new(4000, Currency::PLN))
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use crate::error::AppError;
+use crate::money::Money;
+use crate::{AppState, Repository, SqliteInteger};
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use std::fmt::{Display, Formatter};
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+fn get_weekday_name(a: Weekday) -> &'static str {
+    match a {
+        Weekday::Mon => "poniedziałek",
+        Weekday::Tue => "wtorek",
+        Weekday::Wed => "środa",
+        Weekday::Thu => "czwartek",
+        Weekday::Fri => "piątek",
+        Weekday::Sat => "sobota",
+        Weekday::Sun => "niedziela",
+    }
+}
+
+fn last_day_of_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    NaiveDate::from_ymd_opt(next_year, next_month, 1)
+        .unwrap()
+        .pred_opt()
+        .unwrap()
+        .day()
+}
+
+#[derive(Clone, Copy)]
+pub enum Rule {
+    /// On the given day of every month, or on the last day for shorter months.
+    Monthly {
+        day: u32,
+    },
+    Weekly {
+        weekday: Weekday,
+    },
+}
+
+impl Rule {
+    /// Expected format: the `kind` and `day` columns, e.g. ("monthly", 31) or ("weekly", 1).
+    pub fn parse(kind: &str, day: u32) -> Option<Rule> {
+        match kind {
+            "monthly" if (1..=31).contains(&day) => Some(Rule::Monthly { day }),
+            "weekly" if (1..=7).contains(&day) => Some(Rule::Weekly {
+                weekday: WEEKDAYS[day as usize - 1],
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Rule::Monthly { .. } => "monthly",
+            Rule::Weekly { .. } => "weekly",
+        }
+    }
+
+    pub fn day(&self) -> u32 {
+        match self {
+            Rule::Monthly { day } => *day,
+            Rule::Weekly { weekday } => weekday.number_from_monday(),
+        }
+    }
+
+    /// All dates matching the rule in `from..=until`.
+    fn occurrences(&self, from: NaiveDate, until: NaiveDate) -> Vec<NaiveDate> {
+        let mut dates = Vec::new();
+        match self {
+            Rule::Monthly { day } => {
+                let (mut year, mut month) = (from.year(), from.month());
+                while (year, month) <= (until.year(), until.month()) {
+                    let day = (*day).min(last_day_of_month(year, month));
+                    let date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
+                    if from <= date && date <= until {
+                        dates.push(date);
+                    }
+                    (year, month) = if month == 12 {
+                        (year + 1, 1)
+                    } else {
+                        (year, month + 1)
+                    };
+                }
+            }
+            Rule::Weekly { weekday } => {
+                let offset = (7 + weekday.num_days_from_monday()
+                    - from.weekday().num_days_from_monday())
+                    % 7;
+                let mut date = from + Duration::days(offset.into());
+                while date <= until {
+                    dates.push(date);
+                    date += Duration::days(7);
+                }
+            }
+        }
+        dates
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rule::Monthly { day } => write!(f, "co miesiąc, {}. dnia", day),
+            Rule::Weekly { weekday } => write!(f, "co tydzień, {}", get_weekday_name(*weekday)),
+        }
+    }
+}
+
+/// A template for transactions that repeat, like rent, salary or subscriptions.
+pub struct Recurring {
+    pub id: SqliteInteger,
+    pub name: String,
+    pub value: Money,
+    pub account_id: SqliteInteger,
+    pub category_id: Option<SqliteInteger>,
+    pub rule: Rule,
+    pub start_date: NaiveDate,
+    pub end_date: Option<NaiveDate>,
+    /// The last date for which an expense has already been added.
+    pub last_date: Option<NaiveDate>,
+}
+
+impl Recurring {
+    /// Dates of entries which should exist by `today` but haven't been added yet.
+    pub fn due(&self, today: NaiveDate) -> Vec<NaiveDate> {
+        let from = match self.last_date {
+            Some(last) => (last + Duration::days(1)).max(self.start_date),
+            None => self.start_date,
+        };
+        let until = match self.end_date {
+            Some(end) => end.min(today),
+            None => today,
+        };
+        self.rule.occurrences(from, until)
+    }
+}
+
+/// Periodically add due recurring entries. Catches up on everything missed while the server
+/// was down, since templates remember the last date that's been added.
+pub async fn run_scheduler(state: AppState) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+    loop {
+        interval.tick().await;
+        let today = chrono::offset::Local::now().date_naive();
+        let added = state
+            .run(move |repo| Ok::<_, AppError>(repo.materialize_recurring(today)?))
+            .await;
+        match added {
+            Ok(0) => {}
+            Ok(added) => eprintln!("Added {added} recurring entries."),
+            // Try again at the next tick.
+            Err(e) => eprintln!("Can't add recurring entries: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date;
+    use crate::money::Currency;
+
+    fn monthly(day: u32, start_date: NaiveDate) -> Recurring {
+        Recurring {
+            id: 1,
+            name: "Czynsz".to_string(),
+            value: Money::new(150000, Currency::PLN),
+            account_id: 1,
+            category_id: None,
+            rule: Rule::Monthly { day },
+            start_date,
+            end_date: None,
+            last_date: None,
+        }
+    }
+
+    #[test]
+    fn clamps_monthly_day_to_month_length() {
+        let rule = Rule::Monthly { day: 31 };
+        assert_eq!(
+            rule.occurrences(date(2024, 1, 1), date(2024, 5, 31)),
+            vec![
+                date(2024, 1, 31),
+                date(2024, 2, 29),
+                date(2024, 3, 31),
+                date(2024, 4, 30),
+                date(2024, 5, 31),
+            ]
+        );
+        assert_eq!(
+            rule.occurrences(date(2025, 2, 1), date(2025, 2, 28)),
+            vec![date(2025, 2, 28)]
+        );
+    }
+
+    #[test]
+    fn monthly_occurrences_stay_within_range() {
+        let rule = Rule::Monthly { day: 10 };
+        assert_eq!(
+            rule.occurrences(date(2024, 1, 11), date(2024, 3, 9)),
+            vec![date(2024, 2, 10)]
+        );
+        assert_eq!(
+            rule.occurrences(date(2024, 12, 10), date(2025, 1, 10)),
+            vec![date(2024, 12, 10), date(2025, 1, 10)]
+        );
+    }
+
+    #[test]
+    fn weekly_occurrences() {
+        let rule = Rule::parse("weekly", 3).unwrap();
+        // 2024-01-01 was a Monday.
+        assert_eq!(
+            rule.occurrences(date(2024, 1, 1), date(2024, 1, 24)),
+            vec![
+                date(2024, 1, 3),
+                date(2024, 1, 10),
+                date(2024, 1, 17),
+                date(2024, 1, 24)
+            ]
+        );
+        assert_eq!(
+            rule.occurrences(date(2024, 1, 3), date(2024, 1, 9)),
+            vec![date(2024, 1, 3)]
+        );
+        assert!(rule
+            .occurrences(date(2024, 1, 4), date(2024, 1, 9))
+            .is_empty());
+    }
+
+    #[test]
+    fn parses_rules() {
+        assert!(Rule::parse("monthly", 0).is_none());
+        assert!(Rule::parse("monthly", 32).is_none());
+        assert!(Rule::parse("weekly", 8).is_none());
+        assert!(Rule::parse("yearly", 1).is_none());
+        let rule = Rule::parse("weekly", 7).unwrap();
+        assert_eq!((rule.kind(), rule.day()), ("weekly", 7));
+    }
+
+    #[test]
+    fn catches_up_after_downtime() {
+        let mut recurring = monthly(5, date(2024, 1, 1));
+        recurring.last_date = Some(date(2024, 2, 5));
+        assert_eq!(
+            recurring.due(date(2024, 5, 20)),
+            vec![date(2024, 3, 5), date(2024, 4, 5), date(2024, 5, 5)]
+        );
+        // Nothing is added twice.
+        recurring.last_date = Some(date(2024, 5, 5));
+        assert!(recurring.due(date(2024, 5, 20)).is_empty());
+    }
+
+    #[test]
+    fn starts_on_start_date() {
+        let recurring = monthly(5, date(2024, 1, 6));
+        assert!(recurring.due(date(2024, 2, 4)).is_empty());
+        assert_eq!(recurring.due(date(2024, 2, 5)), vec![date(2024, 2, 5)]);
+    }
+
+    #[test]
+    fn stops_at_end_date() {
+        let mut recurring = monthly(5, date(2024, 1, 1));
+        recurring.end_date = Some(date(2024, 3, 4));
+        assert_eq!(
+            recurring.due(date(2024, 12, 31)),
+            vec![date(2024, 1, 5), date(2024, 2, 5)]
+        );
+        recurring.last_date = Some(date(2024, 2, 5));
+        assert!(recurring.due(date(2024, 12, 31)).is_empty());
+    }
+
+    #[test]
+    fn weekly_due_after_last_date() {
+        let mut recurring = monthly(1, date(2024, 1, 1));
+        recurring.rule = Rule::Weekly {
+            weekday: Weekday::Mon,
+        };
+        recurring.last_date = Some(date(2024, 1, 8));
+        assert_eq!(
+            recurring.due(date(2024, 1, 22)),
+            vec![date(2024, 1, 15), date(2024, 1, 22)]
+        );
+    }
+}